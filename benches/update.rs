@@ -0,0 +1,53 @@
+//! How long it takes to update a world where everything is moving: a 256x256
+//! world with sand over water, so the sand keeps sinking and the water keeps
+//! flowing up around it.
+//!
+//! ```text
+//! cargo bench --no-default-features --bench update
+//! ```
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use sand::*;
+
+const SIZE: usize = 256;
+const CHUNK_SIZE: usize = 16;
+const TICKS: usize = 10;
+
+fn sand_over_water() -> (World, Frame) {
+    let mut world = World::with_seed(SIZE, SIZE, CHUNK_SIZE, 0);
+    for y in 1..SIZE - 1 {
+        for x in 1..SIZE - 1 {
+            let particle_type = if y < SIZE / 2 {
+                ParticleType::Sand
+            } else {
+                ParticleType::Water
+            };
+            world.add_new_particle(particle_type, (x, y), false);
+        }
+    }
+    let mut frame = Frame::for_world(&world);
+    world.draw_and_refresh(&mut frame, false);
+    (world, frame)
+}
+
+fn update(c: &mut Criterion) {
+    let mut group = c.benchmark_group("update");
+    group.sample_size(20);
+    group.bench_function("sand over water", |b| {
+        b.iter_batched(
+            sand_over_water,
+            |(mut world, mut frame)| {
+                for _ in 0..TICKS {
+                    world.update_all();
+                    world.draw_and_refresh(&mut frame, false);
+                }
+                world
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, update);
+criterion_main!(benches);
@@ -0,0 +1,184 @@
+//! Materials that need more than their properties and reactions can describe
+//! get a `ParticleBehavior`, written in Rust. Behaviours are registered under
+//! a name with `register_behavior`, and a material picks one with
+//! `behavior = "name"` in its [[material]] entry.
+
+use super::*;
+use std::sync::Mutex;
+
+/// Hooks called on a particle at points in its update. Everything has a
+/// default that does nothing, so a behaviour only implements the ones it
+/// needs.
+pub trait ParticleBehavior: std::fmt::Debug + Send + Sync {
+    /// Called every tick the particle updates, after it's moved and before
+    /// it reacts with its neighbours.
+    fn update(&self, _particle: &mut Particle, _api: &mut WorldApi) {}
+
+    /// Called before the particle moves by `dxdy`. Returning false stops it
+    /// moving that way.
+    fn premove(&self, _particle: &mut Particle, _dxdy: I8Vec2, _api: &mut WorldApi) -> bool {
+        true
+    }
+
+    /// Called when the particle catches fire.
+    fn on_ignite(&self, _particle: &mut Particle, _api: &mut WorldApi) {}
+
+    /// Called when the particle turns into Empty during its own update, by
+    /// burning out, being worn away, reacting or `Particle::destroy`. The
+    /// particle has already been removed from the world.
+    fn on_destroy(&self, _particle: &Particle, _api: &mut WorldApi) {}
+}
+
+// ─── Built-in behaviours ─────────────────────────────────────────────────────
+
+/// Spreads into empty space while it's watered, passing the water on to the
+/// fungus next to it otherwise.
+#[derive(Debug)]
+pub struct FungusBehavior;
+
+impl ParticleBehavior for FungusBehavior {
+    fn update(&self, particle: &mut Particle, api: &mut WorldApi) {
+        particle.grow_fungus(api);
+    }
+}
+
+/// Sprouts into a stem with a root under it once it's watered and lying on
+/// soil.
+#[derive(Debug)]
+pub struct SeedBehavior;
+
+impl ParticleBehavior for SeedBehavior {
+    fn update(&self, particle: &mut Particle, api: &mut WorldApi) {
+        particle.sprout(api);
+    }
+}
+
+/// Stems, leaves and roots: passes water around the plant, grows with it,
+/// and wilts into compost without it. Burns down to compost too.
+#[derive(Debug)]
+pub struct PlantBehavior;
+
+impl ParticleBehavior for PlantBehavior {
+    fn update(&self, particle: &mut Particle, api: &mut WorldApi) {
+        particle.grow_plant(api);
+    }
+
+    fn on_destroy(&self, particle: &Particle, api: &mut WorldApi) {
+        if particle.is_burning() {
+            api.replace_with_new((0, 0), ParticleType::Compost);
+        }
+    }
+}
+
+/// Goes out as soon as it stops burning.
+#[derive(Debug)]
+pub struct FlameBehavior;
+
+impl ParticleBehavior for FlameBehavior {
+    fn update(&self, particle: &mut Particle, api: &mut WorldApi) {
+        if !particle.is_burning() {
+            particle.destroy(api);
+        }
+    }
+}
+
+// ─── Dispatch ────────────────────────────────────────────────────────────────
+
+/// The behaviour a material runs. The built-in ones are called directly, so
+/// only behaviours from `register_behavior` go through a vtable.
+#[derive(Debug, Clone, Copy)]
+pub enum Behavior {
+    Fungus,
+    Flame,
+    Seed,
+    Plant,
+    Registered(&'static dyn ParticleBehavior),
+}
+
+impl Behavior {
+    pub(crate) fn update(self, particle: &mut Particle, api: &mut WorldApi) {
+        match self {
+            Behavior::Fungus => FungusBehavior.update(particle, api),
+            Behavior::Flame => FlameBehavior.update(particle, api),
+            Behavior::Seed => SeedBehavior.update(particle, api),
+            Behavior::Plant => PlantBehavior.update(particle, api),
+            Behavior::Registered(behavior) => behavior.update(particle, api),
+        }
+    }
+
+    pub(crate) fn premove(self, particle: &mut Particle, dxdy: I8Vec2, api: &mut WorldApi) -> bool {
+        match self {
+            Behavior::Fungus => FungusBehavior.premove(particle, dxdy, api),
+            Behavior::Flame => FlameBehavior.premove(particle, dxdy, api),
+            Behavior::Seed => SeedBehavior.premove(particle, dxdy, api),
+            Behavior::Plant => PlantBehavior.premove(particle, dxdy, api),
+            Behavior::Registered(behavior) => behavior.premove(particle, dxdy, api),
+        }
+    }
+
+    pub(crate) fn on_ignite(self, particle: &mut Particle, api: &mut WorldApi) {
+        match self {
+            Behavior::Fungus => FungusBehavior.on_ignite(particle, api),
+            Behavior::Flame => FlameBehavior.on_ignite(particle, api),
+            Behavior::Seed => SeedBehavior.on_ignite(particle, api),
+            Behavior::Plant => PlantBehavior.on_ignite(particle, api),
+            Behavior::Registered(behavior) => behavior.on_ignite(particle, api),
+        }
+    }
+
+    pub(crate) fn on_destroy(self, particle: &Particle, api: &mut WorldApi) {
+        match self {
+            Behavior::Fungus => FungusBehavior.on_destroy(particle, api),
+            Behavior::Flame => FlameBehavior.on_destroy(particle, api),
+            Behavior::Seed => SeedBehavior.on_destroy(particle, api),
+            Behavior::Plant => PlantBehavior.on_destroy(particle, api),
+            Behavior::Registered(behavior) => behavior.on_destroy(particle, api),
+        }
+    }
+}
+
+// ─── Registration ────────────────────────────────────────────────────────────
+
+type NamedBehavior = (String, &'static dyn ParticleBehavior);
+
+static BEHAVIORS: Mutex<Vec<NamedBehavior>> = Mutex::new(vec![]);
+
+const BUILTIN_BEHAVIORS: [(&str, Behavior); 4] = [
+    ("fungus", Behavior::Fungus),
+    ("flame", Behavior::Flame),
+    ("seed", Behavior::Seed),
+    ("plant", Behavior::Plant),
+];
+
+/// Make a behaviour available to materials as `behavior = "name"`. Has to
+/// happen before materials are loaded (or anything asks about a particle
+/// type). Registering a name that's already taken, including the built-in
+/// "fungus", "flame", "seed" and "plant", replaces it.
+pub fn register_behavior<B: ParticleBehavior + 'static>(
+    name: &str,
+    behavior: B,
+) -> Result<(), MaterialError> {
+    if registry_loaded() {
+        return Err(MaterialError::AlreadyLoaded);
+    }
+    // Behaviours live for as long as the registry does, which is forever
+    let behavior: &'static dyn ParticleBehavior = Box::leak(Box::new(behavior));
+    let mut behaviors = BEHAVIORS.lock().unwrap();
+    behaviors.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+    behaviors.push((name.to_owned(), behavior));
+    Ok(())
+}
+
+pub(crate) fn find_behavior(name: &str) -> Option<Behavior> {
+    let behaviors = BEHAVIORS.lock().unwrap();
+    let registered = behaviors
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|&(_, behavior)| Behavior::Registered(behavior));
+    registered.or_else(|| {
+        BUILTIN_BEHAVIORS
+            .into_iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, behavior)| behavior)
+    })
+}
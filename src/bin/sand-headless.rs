@@ -0,0 +1,233 @@
+//! Run a saved world for a number of ticks without opening a window, then
+//! write it back out along with a PNG of how it ended up.
+//!
+//! ```text
+//! sand-headless <world file> <ticks> [-o <output world>] [--png <output png>]
+//!               [--record <gif or directory>] [--scale <pixels per particle>]
+//!               [--materials <materials file>] [--parallel]
+//! sand-headless --replay <replay file> [-o <output world>] [...]
+//! ```
+//!
+//! By default the world is written back over the input file and the PNG goes
+//! next to it with a `.png` extension. With `--record`, every tick is also
+//! written to an animated GIF or a directory of numbered PNGs. `--parallel`
+//! updates chunks on several threads. When built with the `scripting` feature,
+//! the scripts in the `scripts` directory next to the materials file are
+//! registered as behaviours first.
+//!
+//! With `--replay`, a session recorded in the app is played back instead, for
+//! as many ticks as it was recorded for. The world goes next to the replay
+//! with a `.sand` extension unless `-o` says otherwise.
+
+use sand::*;
+use std::path::PathBuf;
+use std::process::exit;
+
+const USAGE: &str = "usage: sand-headless <world file> <ticks> [-o <output world>] \
+[--png <output png>] [--record <gif or directory>] [--scale <pixels per particle>] \
+[--materials <materials file>] [--parallel]
+       sand-headless --replay <replay file> [-o <output world>] [--png <output png>] \
+[--record <gif or directory>] [--scale <pixels per particle>] [--materials <materials file>]";
+
+enum Input {
+    World { path: PathBuf, ticks: u64 },
+    Replay(PathBuf),
+}
+
+struct Args {
+    input: Input,
+    output: PathBuf,
+    png: PathBuf,
+    record: Option<PathBuf>,
+    scale: usize,
+    materials: Option<PathBuf>,
+    parallel: bool,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut positional = vec![];
+    let mut output = None;
+    let mut png = None;
+    let mut record = None;
+    let mut scale = 1;
+    let mut materials = None;
+    let mut parallel = false;
+    let mut replay = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => {
+                output = Some(PathBuf::from(args.next().ok_or("missing path after -o")?));
+            }
+            "--png" => {
+                png = Some(PathBuf::from(
+                    args.next().ok_or("missing path after --png")?,
+                ));
+            }
+            "--record" => {
+                record = Some(PathBuf::from(
+                    args.next().ok_or("missing path after --record")?,
+                ));
+            }
+            "--scale" => {
+                let value = args.next().ok_or("missing number after --scale")?;
+                scale = value
+                    .parse()
+                    .map_err(|_| format!("scale must be a whole number, got {}", value))?;
+            }
+            "--materials" => {
+                materials = Some(PathBuf::from(
+                    args.next().ok_or("missing path after --materials")?,
+                ));
+            }
+            "--replay" => {
+                replay = Some(PathBuf::from(
+                    args.next().ok_or("missing path after --replay")?,
+                ));
+            }
+            "--parallel" => parallel = true,
+            "-h" | "--help" => return Err(USAGE.to_owned()),
+            _ => positional.push(arg),
+        }
+    }
+
+    let (input, default_output) = match replay {
+        Some(path) => {
+            if !positional.is_empty() {
+                return Err(USAGE.to_owned());
+            }
+            if parallel {
+                return Err("--parallel can't be used with --replay, which records it".to_owned());
+            }
+            let default_output = path.with_extension("sand");
+            (Input::Replay(path), default_output)
+        }
+        None => {
+            if positional.len() != 2 {
+                return Err(USAGE.to_owned());
+            }
+            let path = PathBuf::from(&positional[0]);
+            let ticks = positional[1]
+                .parse()
+                .map_err(|_| format!("ticks must be a whole number, got {}", positional[1]))?;
+            (
+                Input::World {
+                    path: path.clone(),
+                    ticks,
+                },
+                path,
+            )
+        }
+    };
+    let output = output.unwrap_or(default_output);
+    let png = png.unwrap_or_else(|| output.with_extension("png"));
+
+    Ok(Args {
+        input,
+        output,
+        png,
+        record,
+        scale,
+        materials,
+        parallel,
+    })
+}
+
+fn main() {
+    let args = parse_args().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        exit(2);
+    });
+
+    if let Some(path) = &args.materials {
+        #[cfg(feature = "scripting")]
+        {
+            let scripts = path.with_file_name("scripts");
+            if scripts.is_dir() {
+                if let Err(e) = register_script_behaviors(&scripts) {
+                    eprintln!("Couldn't load scripts from {}: {}", scripts.display(), e);
+                    exit(1);
+                }
+            }
+        }
+        if let Err(e) = load_materials(path) {
+            eprintln!("Couldn't load {}: {}", path.display(), e);
+            exit(1);
+        }
+    }
+
+    let (mut world, ticks, replay) = match &args.input {
+        Input::World { path, ticks } => {
+            let mut world = World::load_from_file(path).unwrap_or_else(|e| {
+                eprintln!("Couldn't load {}: {}", path.display(), e);
+                exit(1);
+            });
+            world.set_parallel(args.parallel);
+            (world, *ticks, None)
+        }
+        Input::Replay(path) => {
+            let replay = Replay::load_from_file(path).unwrap_or_else(|e| {
+                eprintln!("Couldn't load {}: {}", path.display(), e);
+                exit(1);
+            });
+            let world = replay.world().unwrap_or_else(|e| {
+                eprintln!("Couldn't play {}: {}", path.display(), e);
+                exit(1);
+            });
+            (world, replay.ticks(), Some(replay))
+        }
+    };
+    let apply_edits = |world: &mut World, tick: u64| {
+        if let Some(replay) = &replay {
+            replay.apply_edits(world, tick);
+        }
+    };
+
+    // draw_and_refresh is what clears each particle's updated flag between
+    // ticks, so it has to be called even though nothing is on screen.
+    let mut frame = Frame::for_world(&world);
+    let mut recorder = args.record.as_ref().map(|path| {
+        Recorder::to_path(path, frame.width(), frame.height(), args.scale).unwrap_or_else(|e| {
+            eprintln!("Couldn't record to {}: {}", path.display(), e);
+            exit(1);
+        })
+    });
+    let mut record = |frame: &Frame| {
+        if let Some(recorder) = recorder.as_mut() {
+            if let Err(e) = recorder.record(frame.pixels()) {
+                eprintln!("Couldn't record frame: {}", e);
+                exit(1);
+            }
+        }
+    };
+
+    for tick in 0..ticks {
+        apply_edits(&mut world, tick);
+        world.draw_and_refresh(&mut frame, false);
+        record(&frame);
+        world.update_all();
+    }
+    apply_edits(&mut world, ticks);
+    world.draw_and_refresh(&mut frame, false);
+    record(&frame);
+
+    #[cfg(feature = "scripting")]
+    for e in take_script_errors() {
+        eprintln!("Script error: {}", e);
+    }
+
+    if let Some(Err(e)) = recorder.map(Recorder::finish) {
+        eprintln!("Couldn't finish recording: {}", e);
+        exit(1);
+    }
+
+    if let Err(e) = world.save_to_file(&args.output) {
+        eprintln!("Couldn't save {}: {}", args.output.display(), e);
+        exit(1);
+    }
+    if let Err(e) = frame.save_png(&args.png) {
+        eprintln!("Couldn't write {}: {}", args.png.display(), e);
+        exit(1);
+    }
+}
@@ -0,0 +1,75 @@
+use super::*;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// A `Renderer` that just keeps the world as an RGBA image in memory, one
+/// pixel per particle. Used when there's no window, e.g. to write out a PNG of
+/// a world after running it headless.
+///
+/// Like the screen buffer in the app, only chunks that changed get redrawn, so
+/// the same frame should be passed to `World::draw_and_refresh` every tick.
+/// Sources and portals aren't drawn.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Frame {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![255; 4 * width * height],
+        }
+    }
+
+    pub fn for_world(world: &World) -> Self {
+        Frame::new(world.width(), world.height())
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Row-major RGBA bytes.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn write_png<W: Write>(&self, writer: W) -> Result<(), png::EncodingError> {
+        let mut encoder = png::Encoder::new(writer, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&self.pixels)
+    }
+
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Result<(), png::EncodingError> {
+        self.write_png(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl Renderer for Frame {
+    fn update_image_with_particle(&mut self, x: usize, y: usize, width: usize, color: PColor) {
+        let idx = x + y * width;
+        self.pixels[4 * idx] = color.r;
+        self.pixels[4 * idx + 1] = color.g;
+        self.pixels[4 * idx + 2] = color.b;
+    }
+
+    fn draw_screen(&mut self, _world_width: u16, _world_height: u16) {}
+
+    fn debug_chunk(&self, _x: usize, _y: usize, _width: usize, _height: usize, _text: &str) {}
+
+    fn draw_source(&self, _x: usize, _y: usize, _particle_type: ParticleType, _replaces: bool) {}
+
+    fn draw_portal(&self, _x: usize, _y: usize, _direction: Direction, _color: PColor) {}
+
+    fn draw_fan(&self, _x: usize, _y: usize, _direction: Direction, _strength: u8) {}
+}
@@ -1,7 +1,8 @@
+use serde::{Deserialize, Serialize};
 use std::ops::{Add, AddAssign, Sub, SubAssign};
 
 // ─── I8vec2 ────────────────────────────────────────────────────────────────────────────────── ✣ ─
-#[derive(PartialEq, Eq, Clone, Copy)]
+#[derive(PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
 pub struct I8Vec2 {
     pub x: i8,
     pub y: i8,
@@ -190,7 +191,7 @@ impl std::fmt::Debug for I8Vec2 {
 }
 
 // ─── Pcolor ────────────────────────────────────────────────────────────────────────────────── ✣ ─
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PColor {
     pub r: u8,
     pub g: u8,
@@ -212,6 +213,16 @@ impl PColor {
         Self { r, g, b }
     }
 
+    /// Parse a colour written as `#rrggbb` (the `#` is optional).
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let hex = hex.strip_prefix('#').unwrap_or(hex);
+        if hex.len() != 6 {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+        Some(PColor::new(channel(0)?, channel(2)?, channel(4)?))
+    }
+
     // ChatGPT wrote these methods. "The formula used in the implementation is
     // based on the description provided in the Wikipedia article on HSL and
     // HSV."
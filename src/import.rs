@@ -0,0 +1,247 @@
+use super::*;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum ImportError {
+    Io(std::io::Error),
+    Decoding(png::DecodingError),
+    Palette { line: usize, message: String },
+}
+
+impl std::fmt::Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportError::Io(e) => write!(f, "{}", e),
+            ImportError::Decoding(e) => write!(f, "could not decode image: {}", e),
+            ImportError::Palette { line, message } => {
+                write!(f, "palette line {}: {}", line, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+impl From<std::io::Error> for ImportError {
+    fn from(value: std::io::Error) -> Self {
+        ImportError::Io(value)
+    }
+}
+
+impl From<png::DecodingError> for ImportError {
+    fn from(value: png::DecodingError) -> Self {
+        ImportError::Decoding(value)
+    }
+}
+
+/// Maps image colours to particle types. Each pixel becomes whichever entry
+/// has the closest colour.
+#[derive(Debug, Clone)]
+pub struct Palette {
+    entries: Vec<(PColor, ParticleType)>,
+}
+
+impl Default for Palette {
+    /// Every particle type, matched by its base colour, except Empty and
+    /// Border, and Flame and Steam, which would only burn or float away.
+    fn default() -> Self {
+        let left_out = [
+            ParticleType::Border,
+            ParticleType::Empty,
+            ParticleType::Flame,
+            ParticleType::Steam,
+        ];
+        let entries = ParticleType::all()
+            .filter(|t| !left_out.contains(t))
+            .map(|t| (t.properties().base_color, t))
+            .collect();
+        Self { entries }
+    }
+}
+
+impl Palette {
+    /// Parse a palette file. Each line is a hex colour followed by the label
+    /// of a particle type, e.g. `#e2bc80 Sand`. Blank lines and lines starting
+    /// with `//` are ignored.
+    pub fn parse(text: &str) -> Result<Self, ImportError> {
+        let mut entries = vec![];
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
+
+            let error = |message: String| ImportError::Palette {
+                line: i + 1,
+                message,
+            };
+
+            let (hex, label) = line
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| error("expected a colour and a particle type".to_owned()))?;
+            let color = PColor::from_hex(hex)
+                .ok_or_else(|| error(format!("{} is not a colour like #rrggbb", hex)))?;
+            let particle_type = ParticleType::from_label(label.trim())
+                .ok_or_else(|| error(format!("unknown particle type {}", label.trim())))?;
+
+            entries.push((color, particle_type));
+        }
+
+        if entries.is_empty() {
+            return Err(ImportError::Palette {
+                line: 0,
+                message: "palette is empty".to_owned(),
+            });
+        }
+
+        Ok(Self { entries })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ImportError> {
+        Palette::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn nearest(&self, color: PColor) -> ParticleType {
+        let distance_sq = |other: PColor| {
+            let dr = color.r as i32 - other.r as i32;
+            let dg = color.g as i32 - other.g as i32;
+            let db = color.b as i32 - other.b as i32;
+            dr * dr + dg * dg + db * db
+        };
+
+        self.entries
+            .iter()
+            .min_by_key(|(entry_color, _)| distance_sq(*entry_color))
+            .map(|(_, particle_type)| *particle_type)
+            .unwrap()
+    }
+}
+
+impl World {
+    /// Build a world from a PNG, one particle per pixel. The world is the size
+    /// of the image rounded up to a whole number of chunks, with the image in
+    /// the top left corner. Transparent pixels are left empty, and the border
+    /// stays where it always is. The world is seeded with `seed`, as for
+    /// `World::with_seed`.
+    pub fn from_image<R: Read>(
+        reader: R,
+        palette: &Palette,
+        chunk_size: usize,
+        seed: u64,
+    ) -> Result<World, ImportError> {
+        let mut decoder = png::Decoder::new(reader);
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info()?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer)?;
+
+        let image_width = info.width as usize;
+        let image_height = info.height as usize;
+        let samples = info.color_type.samples();
+
+        let round_up = |n: usize| n.div_ceil(chunk_size).max(1) * chunk_size;
+        let mut world = World::with_seed(
+            round_up(image_width),
+            round_up(image_height),
+            chunk_size,
+            seed,
+        );
+
+        for y in 0..image_height {
+            let row = &buffer[y * info.line_size..];
+            for x in 0..image_width {
+                let pixel = &row[x * samples..(x + 1) * samples];
+                let (color, alpha) = match info.color_type {
+                    png::ColorType::Grayscale => (PColor::new(pixel[0], pixel[0], pixel[0]), 255),
+                    png::ColorType::GrayscaleAlpha => {
+                        (PColor::new(pixel[0], pixel[0], pixel[0]), pixel[1])
+                    }
+                    png::ColorType::Rgb => (PColor::new(pixel[0], pixel[1], pixel[2]), 255),
+                    png::ColorType::Rgba => (PColor::new(pixel[0], pixel[1], pixel[2]), pixel[3]),
+                    // normalize_to_color8 expands indexed images to RGB(A)
+                    png::ColorType::Indexed => unreachable!(),
+                };
+
+                if alpha < 128 {
+                    continue;
+                }
+                world.add_new_particle(palette.nearest(color), (x, y), true);
+            }
+        }
+
+        Ok(world)
+    }
+
+    pub fn from_image_file<P: AsRef<Path>>(
+        path: P,
+        palette: &Palette,
+        chunk_size: usize,
+        seed: u64,
+    ) -> Result<World, ImportError> {
+        World::from_image(BufReader::new(File::open(path)?), palette, chunk_size, seed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A 4x3 RGBA image. The top row and left column are under the border, so
+    // they're left transparent.
+    fn png(pixels: [[u8; 4]; 6]) -> Vec<u8> {
+        let clear = [255, 255, 255, 0];
+        let mut image = vec![clear; 5];
+        image.extend_from_slice(&pixels[..3]);
+        image.push(clear);
+        image.extend_from_slice(&pixels[3..]);
+
+        let mut bytes = vec![];
+        let mut encoder = png::Encoder::new(&mut bytes, 4, 3);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(image.as_flattened()).unwrap();
+        writer.finish().unwrap();
+        bytes
+    }
+
+    fn rgba(particle_type: ParticleType) -> [u8; 4] {
+        let color = particle_type.properties().base_color;
+        [color.r, color.g, color.b, 255]
+    }
+
+    #[test]
+    fn pixels_become_the_nearest_particle() {
+        let image = png([
+            rgba(ParticleType::Sand),
+            rgba(ParticleType::Water),
+            [255, 255, 255, 0],
+            rgba(ParticleType::Wood),
+            rgba(ParticleType::Empty),
+            rgba(ParticleType::Flame),
+        ]);
+        let world = World::from_image(image.as_slice(), &Palette::default(), 8, 4).unwrap();
+        assert_eq!((world.width(), world.height()), (8, 8));
+        assert_eq!(world.seed(), 4);
+
+        let at = |xy| world.get_particle(xy).particle_type;
+        assert_eq!(at((1, 1)), ParticleType::Sand);
+        assert_eq!(at((2, 1)), ParticleType::Water);
+        assert_eq!(at((3, 1)), ParticleType::Empty);
+        assert_eq!(at((1, 2)), ParticleType::Wood);
+        // Nothing drawn turns into holes or fire
+        assert!(![ParticleType::Empty, ParticleType::Flame].contains(&at((2, 2))));
+        assert!(![ParticleType::Empty, ParticleType::Flame].contains(&at((3, 2))));
+
+        let palette = Palette::parse("#000000 Stone\n#ffffff Salt").unwrap();
+        let world = World::from_image(image.as_slice(), &palette, 8, 4).unwrap();
+        assert_eq!(world.get_particle((1, 1)).particle_type, ParticleType::Salt);
+        assert_eq!(
+            world.get_particle((2, 2)).particle_type,
+            ParticleType::Stone
+        );
+    }
+}
@@ -0,0 +1,27 @@
+//! Falling sand simulation core. Everything in here is independent of any
+//! windowing or rendering library; drawing goes through the [`Renderer`]
+//! trait, which the `sand` binary implements with macroquad.
+
+pub use behavior::*;
+pub use frame::*;
+pub use helpers::*;
+pub use import::*;
+pub use particle::*;
+pub use recorder::*;
+pub use registry::*;
+pub use render::*;
+#[cfg(feature = "scripting")]
+pub use script::*;
+pub use world::*;
+
+mod behavior;
+mod frame;
+mod helpers;
+mod import;
+mod particle;
+mod recorder;
+mod registry;
+mod render;
+#[cfg(feature = "scripting")]
+mod script;
+mod world;
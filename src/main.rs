@@ -1,17 +1,14 @@
 use egui_macroquad::{egui, egui::RichText, *};
-use helpers::*;
 use macroquad::prelude::*;
-use particle::*;
+use sand::*;
 use std::iter::Cycle;
-use world::*;
-
-mod helpers;
-mod particle;
-mod world;
 
 const MINIMUM_UPDATE_TIME: f64 = 1. / 80.;
 // const MINIMUM_UPDATE_TIME: f64 = 1. / 1.;
 const LIMIT_UPDATE_RATE: bool = false;
+const MATERIALS_PATH: &str = "materials.toml";
+#[cfg(feature = "scripting")]
+const SCRIPTS_PATH: &str = "scripts";
 
 fn window_conf() -> Conf {
     Conf {
@@ -34,15 +31,31 @@ async fn main() {
     // let _profiler = dhat::Profiler::new_heap();
     // color_eyre::install()?;
 
+    #[cfg(feature = "scripting")]
+    if std::path::Path::new(SCRIPTS_PATH).is_dir() {
+        if let Err(e) = register_script_behaviors(SCRIPTS_PATH) {
+            println!("Couldn't load scripts from {}: {}", SCRIPTS_PATH, e);
+        }
+    }
+
+    if std::path::Path::new(MATERIALS_PATH).exists() {
+        if let Err(e) = load_materials(MATERIALS_PATH) {
+            println!(
+                "Couldn't load {}, using built-in materials: {}",
+                MATERIALS_PATH, e
+            );
+        }
+    }
+
     let mut color_cycle: Cycle<std::vec::IntoIter<PColor>> = vec![
-        RED.into(),
-        LIME.into(),
-        VIOLET.into(),
-        PINK.into(),
-        ORANGE.into(),
-        GOLD.into(),
-        BEIGE.into(),
-        WHITE.into(),
+        RED.to_pcolor(),
+        LIME.to_pcolor(),
+        VIOLET.to_pcolor(),
+        PINK.to_pcolor(),
+        ORANGE.to_pcolor(),
+        GOLD.to_pcolor(),
+        BEIGE.to_pcolor(),
+        WHITE.to_pcolor(),
     ]
     .into_iter()
     .cycle();
@@ -70,8 +83,13 @@ async fn main() {
         last_placement_type: ParticleType::Sand,
         delete: false,
         replace: false,
+        rigid: false,
+        rigid_cells: vec![],
         debug_mode: false,
-        portal_direction: Direction::Down,
+        multithreaded: false,
+        direction: Direction::Down,
+        fan_strength: 5,
+        wind: 0,
         last_portal_placed: vec![],
         waiting_for_partner_portal: false,
         portal_color: color_cycle.next().unwrap(),
@@ -84,6 +102,14 @@ async fn main() {
         drawing_style: DrawingStyle::Brush,
         draw_xy1: None,
         chunk_size,
+        save_path: "world.sand".to_owned(),
+        image_path: "level.png".to_owned(),
+        palette_path: String::new(),
+        record_path: "recording.gif".to_owned(),
+        record_upscale: false,
+        recorder: None,
+        replay_path: "session.replay".to_owned(),
+        playing: None,
     };
 
     // println!("{:#?}", settings);
@@ -103,6 +129,11 @@ async fn main() {
         let frame_time = time - tic;
         egui_macroquad::ui(|ctx| setup_ui(ctx, &mut settings, &mut world, fps));
         keys_input(&mut settings, &mut world);
+        // A replay being played back makes its own changes to these
+        if settings.playing.is_none() {
+            world.set_parallel(settings.multithreaded);
+            world.set_wind(settings.wind);
+        }
 
         if settings.painter.pixels_per_particle != settings.new_pixels_per_particle {
             settings.rescale();
@@ -111,6 +142,9 @@ async fn main() {
         // ─── Drawing ─────────────────────────────────────────────────────────────
         clear_background(BLACK);
         world.draw_and_refresh(&mut settings.painter, settings.debug_mode);
+        if !settings.paused {
+            settings.record_frame();
+        }
         // ─────────────────────────────────────────────────────────────────────────
 
         cursor_input(&mut settings, &mut world);
@@ -135,7 +169,7 @@ async fn main() {
 
             // ─── Update All Particles ────────────────────────────────────
             if !settings.paused {
-                world.update_all();
+                step(&mut settings, &mut world);
             }
             // ─────────────────────────────────────────────────────────────
         }
@@ -149,6 +183,7 @@ async fn main() {
 #[derive(Debug)]
 struct Settings {
     debug_mode: bool,
+    multithreaded: bool,
     paused: bool,
     brush_size: f32,
     display_fps: bool,
@@ -159,7 +194,14 @@ struct Settings {
     last_placement_type: ParticleType,
     delete: bool,
     replace: bool,
-    portal_direction: Direction,
+    // Whether particles drawn make a rigid body, and the ones drawn so far
+    // in this stroke
+    rigid: bool,
+    rigid_cells: Vec<(usize, usize)>,
+    // Which way new portals face and new fans blow
+    direction: Direction,
+    fan_strength: u8,
+    wind: i8,
     last_portal_placed: Vec<(usize, usize)>,
     waiting_for_partner_portal: bool,
     portal_color: PColor,
@@ -168,6 +210,15 @@ struct Settings {
     new_size: (usize, usize),
     new_pixels_per_particle: f32,
     chunk_size: usize,
+    save_path: String,
+    image_path: String,
+    palette_path: String,
+    record_path: String,
+    record_upscale: bool,
+    recorder: Option<Recorder>,
+    replay_path: String,
+    // The replay being played back, and the tick it's up to
+    playing: Option<(Replay, u64)>,
     mouse_over_gui: bool,
     painter: Painter,
     portal_color_cycle: Cycle<std::vec::IntoIter<PColor>>,
@@ -175,6 +226,142 @@ struct Settings {
 
 impl Settings {
     fn resize_world_and_screen(&mut self) -> World {
+        self.resize_screen();
+        World::new(self.new_size.0, self.new_size.1, self.chunk_size)
+    }
+
+    fn load_world(&mut self) -> Option<World> {
+        match World::load_from_file(&self.save_path) {
+            Ok(world) => {
+                self.fit_screen_to(&world);
+                Some(world)
+            }
+            Err(e) => {
+                println!("Couldn't load {}: {}", self.save_path, e);
+                None
+            }
+        }
+    }
+
+    fn import_world(&mut self) -> Option<World> {
+        let palette = if self.palette_path.is_empty() {
+            Palette::default()
+        } else {
+            match Palette::load(&self.palette_path) {
+                Ok(palette) => palette,
+                Err(e) => {
+                    println!("Couldn't load palette {}: {}", self.palette_path, e);
+                    return None;
+                }
+            }
+        };
+
+        let seed = ::rand::random();
+        match World::from_image_file(&self.image_path, &palette, self.chunk_size, seed) {
+            Ok(world) => {
+                self.fit_screen_to(&world);
+                Some(world)
+            }
+            Err(e) => {
+                println!("Couldn't import {}: {}", self.image_path, e);
+                None
+            }
+        }
+    }
+
+    fn fit_screen_to(&mut self, world: &World) {
+        self.new_size = (world.width(), world.height());
+        self.chunk_size = world.chunk_size();
+        self.resize_screen();
+    }
+
+    fn save_world(&self, world: &World) {
+        if let Err(e) = world.save_to_file(&self.save_path) {
+            println!("Couldn't save {}: {}", self.save_path, e);
+        }
+    }
+
+    fn start_recording(&mut self, world: &World) {
+        let scale = if self.record_upscale {
+            self.painter.pixels_per_particle as usize
+        } else {
+            1
+        };
+
+        match Recorder::to_path(&self.record_path, world.width(), world.height(), scale) {
+            Ok(recorder) => self.recorder = Some(recorder),
+            Err(e) => println!("Couldn't record to {}: {}", self.record_path, e),
+        }
+    }
+
+    fn stop_recording(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            let frames = recorder.frames_written();
+            match recorder.finish() {
+                Ok(()) => println!("Recorded {} frames to {}", frames, self.record_path),
+                Err(e) => println!("Couldn't finish recording {}: {}", self.record_path, e),
+            }
+        }
+    }
+
+    fn record_frame(&mut self) {
+        if let Some(recorder) = self.recorder.as_mut() {
+            if let Err(e) = recorder.record(&self.painter.screen_buffer) {
+                println!("Couldn't record frame: {}", e);
+                self.stop_recording();
+            }
+        }
+    }
+
+    fn stop_recording_inputs(&mut self, world: &mut World) {
+        if let Some(replay) = world.stop_recording_edits() {
+            match replay.save_to_file(&self.replay_path) {
+                Ok(()) => println!(
+                    "Recorded {} ticks of input to {}",
+                    replay.ticks(),
+                    self.replay_path
+                ),
+                Err(e) => println!("Couldn't save {}: {}", self.replay_path, e),
+            }
+        }
+    }
+
+    fn load_replay(&mut self) -> Option<(Replay, World)> {
+        let replay = match Replay::load_from_file(&self.replay_path) {
+            Ok(replay) => replay,
+            Err(e) => {
+                println!("Couldn't load {}: {}", self.replay_path, e);
+                return None;
+            }
+        };
+        match replay.world() {
+            Ok(world) => {
+                self.fit_screen_to(&world);
+                // Otherwise the checkbox would switch it straight back
+                self.multithreaded = world.parallel();
+                Some((replay, world))
+            }
+            Err(e) => {
+                println!("Couldn't play {}: {}", self.replay_path, e);
+                None
+            }
+        }
+    }
+
+    // Undo/redo can take away (or bring back) portals still waiting for their
+    // partners, so start the next pair from scratch
+    fn forget_unpartnered_portals(&mut self) {
+        if !self.last_portal_placed.is_empty() {
+            self.last_portal_placed.clear();
+            self.waiting_for_partner_portal = false;
+            self.portal_color = self.portal_color_cycle.next().unwrap();
+        }
+    }
+
+    fn resize_screen(&mut self) {
+        // The recording can't change size part way through
+        self.stop_recording();
+
         self.painter = Painter::new(
             self.painter.world_pxmin,
             self.painter.world_pymin,
@@ -188,8 +375,6 @@ impl Settings {
 
         self.last_portal_placed = vec![];
         self.waiting_for_partner_portal = false;
-
-        World::new(self.new_size.0, self.new_size.1, self.chunk_size)
     }
 
     fn rescale(&mut self) {
@@ -240,9 +425,7 @@ impl Painter {
         world_width: usize,
         world_height: usize,
     ) -> Self {
-        let screen_buffer: Vec<u8> = std::iter::repeat(255)
-            .take(4 * world_width * world_height)
-            .collect();
+        let screen_buffer: Vec<u8> = vec![255; 4 * world_width * world_height];
 
         let screen_texture =
             Texture2D::from_rgba8(world_width as u16, world_height as u16, &screen_buffer);
@@ -313,6 +496,65 @@ impl Painter {
         // draw_texture(self.particle_texture, px, py, color);
     }
 
+    fn draw_source_with_color(&self, x: usize, y: usize, color: Color, replaces: bool, sink: bool) {
+        let (px, py) = self.xy_to_pixels(x, y);
+        draw_rectangle(
+            px,
+            py,
+            self.pixels_per_particle,
+            self.pixels_per_particle,
+            color,
+        );
+
+        // if !empty {
+        let hatch_color = if replaces || sink {
+            Color::new(0.0, 0.0, 0.0, 0.2)
+        } else {
+            Color::new(1.0, 1.0, 1.0, 0.5)
+        };
+
+        draw_line(
+            px,
+            py,
+            px + self.pixels_per_particle,
+            py + self.pixels_per_particle,
+            1.0,
+            hatch_color,
+        );
+    }
+
+    fn draw_portal_with_color(&self, x: usize, y: usize, direction: Direction, color: Color) {
+        let (px, py) = self.xy_to_pixels(x, y);
+        // draw_line()
+        let pix_per = self.pixels_per_particle;
+        let thickness = pix_per / 4.0;
+        let (ptx, pty, w, h): (f32, f32, f32, f32) = match direction {
+            Direction::Up => (px, py, pix_per, thickness),
+            Direction::Right => (px + pix_per - thickness, py, thickness, pix_per),
+            Direction::Down => (px, py + pix_per - thickness, pix_per, thickness),
+            Direction::Left => (px, py, thickness, pix_per),
+        };
+
+        draw_rectangle(ptx, pty, w, h, color);
+    }
+
+    fn draw_fan_with_color(&self, x: usize, y: usize, direction: Direction, color: Color) {
+        let (px, py) = self.xy_to_pixels(x, y);
+        let pix_per = self.pixels_per_particle;
+        // A triangle pointing the way it blows
+        let (tip, base1, base2) = match direction {
+            Direction::Up => ((0.5, 0.0), (0.0, 1.0), (1.0, 1.0)),
+            Direction::Right => ((1.0, 0.5), (0.0, 0.0), (0.0, 1.0)),
+            Direction::Down => ((0.5, 1.0), (0.0, 0.0), (1.0, 0.0)),
+            Direction::Left => ((0.0, 0.5), (1.0, 0.0), (1.0, 1.0)),
+        };
+        let corner = |(cx, cy): (f32, f32)| vec2(px + cx * pix_per, py + cy * pix_per);
+
+        draw_triangle(corner(tip), corner(base1), corner(base2), color);
+    }
+}
+
+impl Renderer for Painter {
     fn update_image_with_particle(&mut self, x: usize, y: usize, width: usize, color: PColor) {
         let idx = x + y * width;
         self.screen_buffer[4 * idx] = color.r;
@@ -355,53 +597,40 @@ impl Painter {
         draw_text(text, px, py + ph / 2.0, 16.0, WHITE);
     }
 
-    fn draw_source(&self, x: usize, y: usize, color: Color, replaces: bool, sink: bool) {
-        let (px, py) = self.xy_to_pixels(x, y);
-        draw_rectangle(
-            px,
-            py,
-            self.pixels_per_particle,
-            self.pixels_per_particle,
-            color,
-        );
+    fn draw_source(&self, x: usize, y: usize, particle_type: ParticleType, replaces: bool) {
+        let mut color = particle_type.properties().base_color.to_color();
+        color.a = 0.5;
+        color.r -= 0.1;
+        color.g -= 0.1;
+        color.b -= 0.1;
 
-        // if !empty {
-        let hatch_color = if replaces || sink {
-            Color::new(0.0, 0.0, 0.0, 0.2)
-        } else {
-            Color::new(1.0, 1.0, 1.0, 0.5)
-        };
-
-        draw_line(
-            px,
-            py,
-            px + self.pixels_per_particle,
-            py + self.pixels_per_particle,
-            1.0,
-            hatch_color,
-        );
+        self.draw_source_with_color(x, y, color, replaces, particle_type == ParticleType::Empty);
     }
 
-    fn draw_portal(&self, x: usize, y: usize, direction: Direction, color: Color) {
-        let (px, py) = self.xy_to_pixels(x, y);
-        // draw_line()
-        let pix_per = self.pixels_per_particle;
-        let thickness = pix_per / 4.0;
-        let (ptx, pty, w, h): (f32, f32, f32, f32) = match direction {
-            Direction::Up => (px, py, pix_per, thickness),
-            Direction::Right => (px + pix_per - thickness, py, thickness, pix_per),
-            Direction::Down => (px, py + pix_per - thickness, pix_per, thickness),
-            Direction::Left => (px, py, thickness, pix_per),
-        };
+    fn draw_portal(&self, x: usize, y: usize, direction: Direction, color: PColor) {
+        self.draw_portal_with_color(x, y, direction, color.to_color());
+    }
 
-        draw_rectangle(ptx, pty, w, h, color);
+    fn draw_fan(&self, x: usize, y: usize, direction: Direction, strength: u8) {
+        // Stronger fans are more solid
+        let alpha = 0.3 + 0.7 * strength as f32 / MAX_FAN_STRENGTH as f32;
+        self.draw_fan_with_color(x, y, direction, Color::new(0.8, 0.9, 1.0, alpha));
     }
 }
 
 // ─── Handle Input ──────────────────────────────────────────────────────────────────────────── ✣ ─
 fn cursor_input(settings: &mut Settings, world: &mut World) {
+    // Edits made while a replay is playing would throw it off
+    if settings.playing.is_some() {
+        return;
+    }
     let (px, py) = mouse_position();
 
+    // A brush stroke is one edit for undo, wherever the mouse is let go
+    if !is_mouse_button_down(MouseButton::Left) {
+        end_stroke(settings, world);
+    }
+
     if px > settings.painter.world_pxmin
         && px < screen_width()
         && py > settings.painter.world_pymin
@@ -428,7 +657,9 @@ fn cursor_input(settings: &mut Settings, world: &mut World) {
                     if let Some(xy1) = settings.draw_xy1 {
                         // If we clicked and the first point has already been set,
                         // create particles along the line
+                        world.begin_edit();
                         fill_brush_along_line(settings, world, xy1, (mousex, mousey));
+                        end_stroke(settings, world);
                         settings.draw_xy1 = None;
                     } else {
                         // If we clicked and the first point hasn't been set, set
@@ -467,6 +698,7 @@ fn cursor_input(settings: &mut Settings, world: &mut World) {
 
             DrawingStyle::Brush => {
                 if is_mouse_button_pressed(MouseButton::Left) {
+                    world.begin_edit();
                     settings.draw_xy1 = Some((mousex, mousey));
                 }
 
@@ -491,7 +723,7 @@ fn cursor_input(settings: &mut Settings, world: &mut World) {
 
                 // Check whether the location/size of the portal we're trying to place is valid
                 settings.portal_placement_valid = true;
-                match settings.portal_direction {
+                match settings.direction {
                     Direction::Up | Direction::Down => {
                         brushy_min = mousey;
                         brushy_max = mousey + 1;
@@ -521,9 +753,11 @@ fn cursor_input(settings: &mut Settings, world: &mut World) {
                 }
 
                 if is_mouse_button_pressed(MouseButton::Left) {
+                    world.begin_edit();
                     fill_brush(
                         settings, world, brushx_min, brushx_max, brushy_min, brushy_max,
                     );
+                    world.end_edit();
                 } else {
                     highlight_brush(
                         settings, world, brushx_min, brushx_max, brushy_min, brushy_max,
@@ -534,12 +768,20 @@ fn cursor_input(settings: &mut Settings, world: &mut World) {
     }
 }
 
+// Make whatever was drawn into a rigid body if it should be, and finish the edit
+fn end_stroke(settings: &mut Settings, world: &mut World) {
+    if !settings.rigid_cells.is_empty() {
+        world.add_rigid_body(&std::mem::take(&mut settings.rigid_cells));
+    }
+    world.end_edit();
+}
+
 fn keys_input(settings: &mut Settings, world: &mut World) {
     // Advance on "A" if paused
     if is_key_pressed(KeyCode::A) && settings.paused {
         println!("advance");
         world.draw_and_refresh(&mut settings.painter, settings.debug_mode);
-        world.update_all();
+        step(settings, world);
     }
     // Pause/Unpause with space
     if is_key_pressed(KeyCode::Space) {
@@ -552,7 +794,30 @@ fn keys_input(settings: &mut Settings, world: &mut World) {
     }
     // Reset on "R"
     if is_key_pressed(KeyCode::R) {
-        *world = settings.resize_world_and_screen();
+        let new_world = settings.resize_world_and_screen();
+        replace_world(settings, world, new_world);
+    }
+    // Reload scripts on F5
+    #[cfg(feature = "scripting")]
+    if is_key_pressed(KeyCode::F5) {
+        let errors = reload_scripts();
+        if errors.is_empty() {
+            println!("Reloaded scripts");
+        }
+        for e in errors {
+            println!("Couldn't reload script: {}", e);
+        }
+    }
+    // Undo on Ctrl+Z, redo on Ctrl+Y or Ctrl+Shift+Z
+    if (is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl))
+        && settings.playing.is_none()
+    {
+        let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+        if is_key_pressed(KeyCode::Y) || (shift && is_key_pressed(KeyCode::Z)) {
+            redo(settings, world);
+        } else if is_key_pressed(KeyCode::Z) {
+            undo(settings, world);
+        }
     }
 
     // Change brush size with mouse wheel
@@ -566,6 +831,50 @@ fn keys_input(settings: &mut Settings, world: &mut World) {
     }
 }
 
+// Update the world once, making any edits a replay being played back made
+// before this update
+fn step(settings: &mut Settings, world: &mut World) {
+    if let Some((replay, tick)) = settings.playing.as_mut() {
+        replay.apply_edits(world, *tick);
+        // So the checkbox and slider don't set them straight back once it's
+        // finished
+        settings.multithreaded = world.parallel();
+        settings.wind = world.wind();
+        if *tick == replay.ticks() {
+            // The session ended before this update
+            println!("Finished playing {}", settings.replay_path);
+            settings.playing = None;
+            return;
+        }
+        *tick += 1;
+    }
+    world.update_all();
+    #[cfg(feature = "scripting")]
+    for e in take_script_errors() {
+        println!("Script error: {}", e);
+    }
+}
+
+// Anything recording or playing back the old world stops
+fn replace_world(settings: &mut Settings, world: &mut World, new_world: World) {
+    settings.stop_recording_inputs(world);
+    settings.playing = None;
+    *world = new_world;
+    settings.wind = world.wind();
+}
+
+fn undo(settings: &mut Settings, world: &mut World) {
+    if world.undo() {
+        settings.forget_unpartnered_portals();
+    }
+}
+
+fn redo(settings: &mut Settings, world: &mut World) {
+    if world.redo() {
+        settings.forget_unpartnered_portals();
+    }
+}
+
 fn apply_fn_in_square<F>(
     xmin: usize,
     xmax: usize,
@@ -631,6 +940,7 @@ fn fill_brush(
         |x, y| {
             if settings.delete {
                 world.delete_source((x, y));
+                world.delete_fan((x, y));
                 world.add_new_particle(ParticleType::Empty, (x, y), settings.replace);
             } else {
                 create_placeable(settings, world, (x, y));
@@ -659,7 +969,7 @@ fn highlight_brush(
 }
 
 fn highlight_particle(settings: &Settings, x: usize, y: usize) {
-    let mut color: Color = settings.placement_type.properties().base_color.into();
+    let mut color = settings.placement_type.properties().base_color.to_color();
     color.a = 0.4;
     settings.painter.draw_particle(x, y, color);
 }
@@ -670,41 +980,51 @@ fn highlight_selected_placeable(settings: &Settings, x: usize, y: usize) {
             highlight_particle(settings, x, y);
         }
         PlaceableSelector::Source => {
-            let mut color: Color = settings.placement_type.properties().base_color.into();
+            let mut color = settings.placement_type.properties().base_color.to_color();
             color.a = 0.4;
             color.r -= 0.1;
             color.g -= 0.1;
             color.b -= 0.1;
             settings
                 .painter
-                .draw_source(x, y, color, settings.sources_replace, false);
+                .draw_source_with_color(x, y, color, settings.sources_replace, false);
         }
         PlaceableSelector::Sink => {
-            let mut color: Color = settings.placement_type.properties().base_color.into();
+            let mut color = settings.placement_type.properties().base_color.to_color();
             color.a = 0.4;
             color.r -= 0.1;
             color.g -= 0.1;
             color.b -= 0.1;
             settings
                 .painter
-                .draw_source(x, y, color, settings.sources_replace, true);
+                .draw_source_with_color(x, y, color, settings.sources_replace, true);
+        }
+        PlaceableSelector::Fan => {
+            settings.painter.draw_fan_with_color(
+                x,
+                y,
+                settings.direction,
+                Color::new(0.8, 0.9, 1.0, 0.4),
+            );
         }
         PlaceableSelector::Portal => {
             if !settings.portal_placement_valid {
                 return;
             }
 
-            let mut color: Color = settings.portal_color.into();
+            let mut color = settings.portal_color.to_color();
             color.a = 0.4;
             settings
                 .painter
-                .draw_portal(x, y, settings.portal_direction, color);
+                .draw_portal_with_color(x, y, settings.direction, color);
         }
     }
 }
 
-fn create_particle(settings: &Settings, world: &mut World, xy: (usize, usize)) {
-    world.add_new_particle(settings.placement_type, xy, settings.replace);
+fn create_particle(settings: &mut Settings, world: &mut World, xy: (usize, usize)) {
+    if world.add_new_particle(settings.placement_type, xy, settings.replace) && settings.rigid {
+        settings.rigid_cells.push(xy);
+    }
 }
 
 fn create_placeable(settings: &mut Settings, world: &mut World, xy: (usize, usize)) {
@@ -723,6 +1043,14 @@ fn create_placeable(settings: &mut Settings, world: &mut World, xy: (usize, usiz
         PlaceableSelector::Sink => {
             world.add_new_source(ParticleType::Empty, xy, true, settings.replace);
         }
+        PlaceableSelector::Fan => {
+            world.add_new_fan(
+                xy,
+                settings.direction,
+                settings.fan_strength,
+                settings.replace,
+            );
+        }
         PlaceableSelector::Portal => {
             if !settings.portal_placement_valid {
                 return;
@@ -744,12 +1072,8 @@ fn create_placeable(settings: &mut Settings, world: &mut World, xy: (usize, usiz
 
             // TODO: Since I'm checking in advance whether there's already a
             // portal there now, checking again here is redundant
-            let added = world.add_new_portal(
-                xy,
-                partner_xy,
-                settings.portal_direction,
-                settings.portal_color.into(),
-            );
+            let added =
+                world.add_new_portal(xy, partner_xy, settings.direction, settings.portal_color);
 
             if added {
                 if !settings.waiting_for_partner_portal {
@@ -810,6 +1134,7 @@ enum PlaceableSelector {
     Particle,
     Source,
     Sink,
+    Fan,
     Portal,
 }
 
@@ -819,17 +1144,14 @@ impl PlaceableSelector {
             PlaceableSelector::Particle => "Particle",
             PlaceableSelector::Source => "Source",
             PlaceableSelector::Sink => "Sink",
+            PlaceableSelector::Fan => "Fan",
             PlaceableSelector::Portal => "Portal",
         }
     }
 }
 
 fn setup_ui(ctx: &egui::Context, settings: &mut Settings, world: &mut World, fps: f64) {
-    if ctx.wants_pointer_input() || ctx.is_pointer_over_area() {
-        settings.mouse_over_gui = true;
-    } else {
-        settings.mouse_over_gui = false;
-    }
+    settings.mouse_over_gui = ctx.wants_pointer_input() || ctx.is_pointer_over_area();
 
     egui::Window::new("")
         // .resizable(false)
@@ -846,7 +1168,26 @@ fn setup_ui(ctx: &egui::Context, settings: &mut Settings, world: &mut World, fps
                     ui.selectable_value(&mut settings.paused, true, "⏸");
                     ui.selectable_value(&mut settings.paused, false, "▶");
                     if ui.button("⏭").clicked() && settings.paused {
-                        world.update_all();
+                        // Every update gets a draw first, like in the main
+                        // loop, so replays of it come out the same
+                        world.draw_and_refresh(&mut settings.painter, settings.debug_mode);
+                        step(settings, world);
+                    }
+                });
+
+                ui.group(|ui| {
+                    let editable = settings.playing.is_none();
+                    if ui
+                        .add_enabled(editable && world.can_undo(), egui::Button::new("Undo"))
+                        .clicked()
+                    {
+                        undo(settings, world);
+                    }
+                    if ui
+                        .add_enabled(editable && world.can_redo(), egui::Button::new("Redo"))
+                        .clicked()
+                    {
+                        redo(settings, world);
                     }
                 });
 
@@ -917,9 +1258,71 @@ fn setup_ui(ctx: &egui::Context, settings: &mut Settings, world: &mut World, fps
                     });
                 ui.end_row();
                 if ui.add(egui::Button::new("Reset/Resize")).clicked() {
-                    *world = settings.resize_world_and_screen();
+                    let new_world = settings.resize_world_and_screen();
+                    replace_world(settings, world, new_world);
+                }
+                ui.end_row();
+                ui.add(egui::TextEdit::singleline(&mut settings.save_path).desired_width(140.0));
+                ui.horizontal(|ui| {
+                    if ui.button("Save").clicked() {
+                        settings.save_world(world);
+                    }
+                    if ui.button("Load").clicked() {
+                        if let Some(loaded) = settings.load_world() {
+                            replace_world(settings, world, loaded);
+                        }
+                    }
+                });
+                ui.end_row();
+                ui.add(egui::TextEdit::singleline(&mut settings.image_path).desired_width(140.0));
+                if ui.button("Import Image").clicked() {
+                    if let Some(imported) = settings.import_world() {
+                        replace_world(settings, world, imported);
+                    }
                 }
                 ui.end_row();
+                ui.add(
+                    egui::TextEdit::singleline(&mut settings.palette_path)
+                        .hint_text("Palette (optional)")
+                        .desired_width(140.0),
+                );
+                ui.end_row();
+                ui.add(egui::TextEdit::singleline(&mut settings.record_path).desired_width(140.0));
+                ui.horizontal(|ui| {
+                    let mut recording = settings.recorder.is_some();
+                    if ui.toggle_value(&mut recording, "⏺ Record").changed() {
+                        if recording {
+                            settings.start_recording(world);
+                        } else {
+                            settings.stop_recording();
+                        }
+                    }
+                    ui.add_enabled(
+                        settings.recorder.is_none(),
+                        egui::Checkbox::new(&mut settings.record_upscale, "Upscale"),
+                    );
+                });
+                ui.end_row();
+                ui.add(egui::TextEdit::singleline(&mut settings.replay_path).desired_width(140.0));
+                ui.horizontal(|ui| {
+                    let mut recording = world.is_recording_edits();
+                    if ui.toggle_value(&mut recording, "⏺ Inputs").changed() {
+                        if recording {
+                            if let Err(e) = world.start_recording_edits() {
+                                println!("Couldn't start recording inputs: {}", e);
+                            }
+                        } else {
+                            settings.stop_recording_inputs(world);
+                        }
+                    }
+                    if ui.button("Replay").clicked() {
+                        if let Some((replay, replayed)) = settings.load_replay() {
+                            replace_world(settings, world, replayed);
+                            settings.playing = Some((replay, 0));
+                        }
+                    }
+                });
+                ui.end_row();
                 // });
             });
 
@@ -933,6 +1336,16 @@ fn setup_ui(ctx: &egui::Context, settings: &mut Settings, world: &mut World, fps
                     ui.checkbox(&mut settings.debug_mode, "");
                     ui.end_row();
 
+                    // Left to the replay while one's playing
+                    let editable = settings.playing.is_none();
+
+                    ui.label("Multithreaded");
+                    ui.add_enabled(
+                        editable,
+                        egui::Checkbox::new(&mut settings.multithreaded, ""),
+                    );
+                    ui.end_row();
+
                     ui.label("New Sources Replace");
                     ui.checkbox(&mut settings.sources_replace, "");
                     ui.end_row();
@@ -941,6 +1354,21 @@ fn setup_ui(ctx: &egui::Context, settings: &mut Settings, world: &mut World, fps
                     ui.checkbox(&mut settings.replace, "");
                     ui.end_row();
 
+                    ui.label("Rigid Bodies");
+                    ui.checkbox(&mut settings.rigid, "");
+                    ui.end_row();
+
+                    ui.label("Fan Strength");
+                    ui.add(egui::Slider::new(
+                        &mut settings.fan_strength,
+                        1..=MAX_FAN_STRENGTH,
+                    ));
+                    ui.end_row();
+
+                    ui.label("Wind");
+                    ui.add_enabled(editable, egui::Slider::new(&mut settings.wind, -5..=5));
+                    ui.end_row();
+
                     ui.label("Brush Size");
                     ui.horizontal(|ui| {
                         ui.add(
@@ -1013,6 +1441,11 @@ fn setup_ui(ctx: &egui::Context, settings: &mut Settings, world: &mut World, fps
                     PlaceableSelector::Sink,
                     PlaceableSelector::Sink.as_str(),
                 );
+                ui.selectable_value(
+                    &mut settings.placeable_selector,
+                    PlaceableSelector::Fan,
+                    PlaceableSelector::Fan.as_str(),
+                );
             });
             // });
             // ui.end_row();
@@ -1041,6 +1474,24 @@ fn setup_ui(ctx: &egui::Context, settings: &mut Settings, world: &mut World, fps
                     particle_selector(ui, ParticleType::Oil, settings);
                     particle_selector(ui, ParticleType::Wood, settings);
                     particle_selector(ui, ParticleType::Acid, settings);
+                    particle_selector(ui, ParticleType::Glass, settings);
+                    particle_selector(ui, ParticleType::Metal, settings);
+                    particle_selector(ui, ParticleType::Battery, settings);
+                    particle_selector(ui, ParticleType::Hydrogen, settings);
+                    particle_selector(ui, ParticleType::Lava, settings);
+                    particle_selector(ui, ParticleType::Stone, settings);
+                    particle_selector(ui, ParticleType::Obsidian, settings);
+                    particle_selector(ui, ParticleType::Ice, settings);
+                    particle_selector(ui, ParticleType::Snow, settings);
+                    particle_selector(ui, ParticleType::Salt, settings);
+                    particle_selector(ui, ParticleType::Brine, settings);
+                    particle_selector(ui, ParticleType::WeakAcid, settings);
+                    particle_selector(ui, ParticleType::Mud, settings);
+                    particle_selector(ui, ParticleType::Seed, settings);
+                    particle_selector(ui, ParticleType::Compost, settings);
+                    for ptype in ParticleType::all().filter(|t| !t.is_builtin()) {
+                        particle_selector(ui, ptype, settings);
+                    }
                 });
             });
             ui.separator();
@@ -1048,21 +1499,21 @@ fn setup_ui(ctx: &egui::Context, settings: &mut Settings, world: &mut World, fps
                 ui.label("");
                 ui.label("");
                 ui.selectable_value(
-                    &mut settings.portal_direction,
+                    &mut settings.direction,
                     Direction::Up,
                     RichText::new("⮉").size(24.0),
                 );
                 ui.label("");
                 ui.end_row();
-                ui.label("Portal\nDirection:");
+                ui.label("Direction:");
                 ui.selectable_value(
-                    &mut settings.portal_direction,
+                    &mut settings.direction,
                     Direction::Left,
                     RichText::new("⮈").size(24.0),
                 );
                 ui.label("");
                 ui.selectable_value(
-                    &mut settings.portal_direction,
+                    &mut settings.direction,
                     Direction::Right,
                     RichText::new("⮊").size(24.0),
                 );
@@ -1070,7 +1521,7 @@ fn setup_ui(ctx: &egui::Context, settings: &mut Settings, world: &mut World, fps
                 ui.label("");
                 ui.label("");
                 ui.selectable_value(
-                    &mut settings.portal_direction,
+                    &mut settings.direction,
                     Direction::Down,
                     RichText::new("⮋").size(24.0),
                 );
@@ -1088,6 +1539,7 @@ fn setup_ui(ctx: &egui::Context, settings: &mut Settings, world: &mut World, fps
         .fixed_pos([settings.painter.world_pxmax, settings.painter.world_pymin])
         .resizable(false)
         .show(ctx, |ui| {
+            ui.label(format!("Seed: {}", world.seed()));
             egui::CollapsingHeader::new("Settings Struct").show(ui, |ui| {
                 ui.label(format!("{:#?}", settings));
             });
@@ -1101,13 +1553,13 @@ fn setup_ui(ctx: &egui::Context, settings: &mut Settings, world: &mut World, fps
 fn particle_selector(ui: &mut egui::Ui, ptype: ParticleType, settings: &mut Settings) {
     // ui.selectable_value(&mut settings.placement_type, ptype, "");
     egui::Frame::none()
-        .fill(ptype.properties().base_color.into())
+        .fill(ptype.properties().base_color.to_egui())
         .show(ui, |ui| {
             // ui.label("");
             ui.selectable_value(
                 &mut settings.placement_type,
                 ptype,
-                RichText::new(ptype.properties().label)
+                RichText::new(&ptype.properties().label)
                     .strong()
                     .monospace()
                     .background_color(egui::Color32::from_black_alpha(150)),
@@ -1117,50 +1569,48 @@ fn particle_selector(ui: &mut egui::Ui, ptype: ParticleType, settings: &mut Sett
         });
 }
 
-impl From<PColor> for Color {
-    fn from(value: PColor) -> Self {
+// PColor lives in the sand library, so these can't be From impls
+trait ToMacroquadColor {
+    fn to_color(&self) -> Color;
+}
+
+impl ToMacroquadColor for PColor {
+    fn to_color(&self) -> Color {
         Color::new(
-            value.r as f32 / 255.0,
-            value.g as f32 / 255.0,
-            value.b as f32 / 255.0,
+            self.r as f32 / 255.0,
+            self.g as f32 / 255.0,
+            self.b as f32 / 255.0,
             1.0,
         )
     }
 }
 
-impl From<Color> for PColor {
-    fn from(value: Color) -> Self {
-        if value.a < 1.0 {
+trait ToPColor {
+    fn to_pcolor(&self) -> PColor;
+}
+
+impl ToPColor for Color {
+    fn to_pcolor(&self) -> PColor {
+        if self.a < 1.0 {
             println!(
                 "WARNING: Converting Color to PColor ignores alpha of {}",
-                value.a
+                self.a
             );
         }
         PColor::new(
-            (value.r * 255.0) as u8,
-            (value.g * 255.0) as u8,
-            (value.b * 255.0) as u8,
+            (self.r * 255.0) as u8,
+            (self.g * 255.0) as u8,
+            (self.b * 255.0) as u8,
         )
     }
 }
 
-impl From<PColor> for egui::Color32 {
-    fn from(value: PColor) -> Self {
-        egui::Color32::from_rgb(value.r, value.g, value.b)
-    }
-}
-
 trait ToEguiColor {
     fn to_egui(&self) -> egui::color::Color32;
 }
 
-impl ToEguiColor for macroquad::color::Color {
+impl ToEguiColor for PColor {
     fn to_egui(&self) -> egui::color::Color32 {
-        egui::Color32::from_rgba_unmultiplied(
-            (self.r * 255.0) as u8,
-            (self.g * 255.0) as u8,
-            (self.b * 255.0) as u8,
-            (self.a * 255.0) as u8,
-        )
+        egui::Color32::from_rgb(self.r, self.g, self.b)
     }
 }
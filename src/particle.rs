@@ -1,10 +1,11 @@
 use super::*;
-use ::rand::{rngs::ThreadRng, Rng};
+use ::rand::Rng;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 // The immutable properties of a particle type
 pub struct ParticleTypeProperties {
-    pub label: &'static str,
+    pub label: String,
     pub base_color: PColor,
     pub weight: f32,
     pub moves: bool,
@@ -12,343 +13,405 @@ pub struct ParticleTypeProperties {
     pub fluid: bool,
     pub terminal_velocity_sq: Option<u16>,
     pub dispersion_rate: Option<u8>,
-    pub flammability: f32,
-    pub wet_flammability: Option<f32>,
+    pub viscosity: f32,
     pub base_fuel: Option<i16>,
     pub base_durability: Option<i16>,
+    pub conductor: bool,
+    pub battery: bool,
+    pub conductivity: f32,
+    pub heat_capacity: f32,
+    pub initial_temperature: f32,
+    pub ignition_temperature: Option<f32>,
+    pub wet_ignition_temperature: Option<f32>,
+    pub burn_temperature: f32,
+    pub heated: Option<PhaseChange>,
+    pub cooled: Option<PhaseChange>,
+    pub shatters_into: Option<ParticleType>,
+    pub explodes: Option<Explosion>,
+    pub behavior: Option<Behavior>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-#[repr(u8)]
-pub enum ParticleType {
-    Border = 0,
-    Concrete = 1,
-    Empty = 2,
-    Sand = 3,
-    Water = 4,
-    Steam = 5,
-    Fungus = 6,
-    Flame = 7,
-    Methane = 8,
-    Gunpowder = 9,
-    Oil = 10,
-    Wood = 11,
-    Acid = 12,
+/// Turn into another particle type once past a temperature (above it for
+/// `heated`, below it for `cooled`).
+#[derive(Debug, Clone, Copy)]
+pub struct PhaseChange {
+    pub temperature: f32,
+    pub becomes: ParticleType,
 }
 
-const PROPERTIES: [ParticleTypeProperties; 13] = [
-    // Border = 0
-    ParticleTypeProperties {
-        label: "Border",
-        base_color: PColor::new(129, 129, 129),
-        weight: f32::INFINITY,
-        moves: false,
-        auto_move: false,
-        fluid: false,
-        terminal_velocity_sq: None,
-        dispersion_rate: None,
-        flammability: 0.0,
-        wet_flammability: None,
-        base_fuel: None,
-        base_durability: None,
-    },
-    // Concrete = 1
-    ParticleTypeProperties {
-        label: "Concrete",
-        base_color: PColor::new(129, 129, 129),
-        weight: f32::INFINITY,
-        moves: false,
-        auto_move: false,
-        fluid: false,
-        terminal_velocity_sq: None,
-        dispersion_rate: None,
-        flammability: 0.0,
-        wet_flammability: None,
-        base_fuel: None,
-        base_durability: Some(100),
-    },
-    // Empty = 2
-    ParticleTypeProperties {
-        label: "Empty",
-        base_color: PColor::new(51, 51, 51),
-        weight: 1.0,
-        moves: false,
-        auto_move: false,
-        fluid: false,
-        terminal_velocity_sq: None,
-        dispersion_rate: None,
-        flammability: 0.0,
-        wet_flammability: None,
-        base_fuel: None,
-        base_durability: None,
-    },
-    // Sand = 3
-    ParticleTypeProperties {
-        label: "Sand",
-        base_color: PColor::new(226, 188, 128),
-        weight: 90.0,
-        moves: true,
-        auto_move: true,
-        fluid: false,
-        terminal_velocity_sq: Some(u16::pow(5, 2)),
-        dispersion_rate: None,
-        flammability: 0.0,
-        wet_flammability: None,
-        base_fuel: None,
-        base_durability: Some(20),
-    },
-    // Water = 4
-    ParticleTypeProperties {
-        label: "Water",
-        base_color: PColor::new(8, 116, 236),
-        weight: 60.0,
-        moves: true,
-        auto_move: true,
-        fluid: true,
-        terminal_velocity_sq: Some(u16::pow(5, 2)),
-        dispersion_rate: Some(5),
-        flammability: 0.0,
-        wet_flammability: None,
-        base_fuel: None,
-        base_durability: None,
-    },
-    // Steam = 5
-    ParticleTypeProperties {
-        label: "Steam",
-        base_color: PColor::new(192, 209, 239),
-        weight: 0.5,
-        moves: true,
-        auto_move: false,
-        fluid: true,
-        terminal_velocity_sq: Some(u16::pow(5, 2)),
-        dispersion_rate: Some(10),
-        flammability: 0.0,
-        wet_flammability: None,
-        base_fuel: None,
-        base_durability: None,
-    },
-    // Fungus = 6
-    ParticleTypeProperties {
-        label: "Fungus",
-        base_color: PColor::new(103, 147, 131),
-        weight: f32::INFINITY,
-        moves: false,
-        auto_move: false,
-        fluid: false,
-        terminal_velocity_sq: None,
-        dispersion_rate: None,
-        flammability: 0.125,
-        wet_flammability: Some(0.015),
-        base_fuel: Some(35),
-        base_durability: Some(10),
-    },
-    // Flame = 7
-    ParticleTypeProperties {
-        label: "Flame",
-        base_color: PColor::new(255, 123, 36),
-        weight: f32::INFINITY,
-        moves: false,
-        auto_move: false,
-        fluid: false,
-        terminal_velocity_sq: None,
-        dispersion_rate: None,
-        flammability: 0.0,
-        wet_flammability: None,
-        base_fuel: Some(0),
-        base_durability: None,
-    },
-    // Methane = 8
-    ParticleTypeProperties {
-        label: "Methane",
-        base_color: PColor::new(148, 119, 165),
-        weight: 0.2,
-        moves: true,
-        auto_move: true,
-        fluid: true,
-        terminal_velocity_sq: Some(u16::pow(5, 2)),
-        dispersion_rate: Some(7),
-        flammability: 0.95,
-        wet_flammability: None,
-        base_fuel: Some(6),
-        base_durability: None,
-    },
-    // Gunpowder = 9
-    ParticleTypeProperties {
-        label: "Gunpowder",
-        base_color: PColor::new(0, 0, 0),
-        weight: 90.0,
-        moves: true,
-        auto_move: true,
-        fluid: false,
-        terminal_velocity_sq: Some(u16::pow(5, 2)),
-        dispersion_rate: None,
-        flammability: 0.6,
-        wet_flammability: None,
-        base_fuel: Some(35),
-        base_durability: Some(20),
-    },
-    // Oil = 10
-    ParticleTypeProperties {
-        label: "Oil",
-        base_color: PColor::new(112, 87, 50),
-        weight: 50.0,
-        moves: true,
-        auto_move: true,
-        fluid: true,
-        terminal_velocity_sq: Some(u16::pow(5, 2)),
-        dispersion_rate: Some(3),
-        flammability: 0.9,
-        wet_flammability: None,
-        base_fuel: Some(25),
-        base_durability: None,
-    },
-    // Wood = 11
-    ParticleTypeProperties {
-        label: "Wood",
-        base_color: PColor::new(98, 57, 35),
-        weight: f32::INFINITY,
-        moves: false,
-        auto_move: false,
-        fluid: false,
-        terminal_velocity_sq: None,
-        dispersion_rate: None,
-        flammability: 0.1,
-        wet_flammability: None,
-        base_fuel: Some(200),
-        base_durability: Some(70),
-    },
-    // Acid = 12
-    ParticleTypeProperties {
-        label: "Acid",
-        base_color: PColor::new(166, 249, 94),
-        weight: 63.0,
-        moves: true,
-        auto_move: true,
-        fluid: true,
-        terminal_velocity_sq: Some(u16::pow(5, 2)),
-        dispersion_rate: Some(1),
-        flammability: 0.0,
-        wet_flammability: None,
-        base_fuel: None,
-        base_durability: Some(50),
-    },
-];
+/// How a material blows up when it burns. Only burning particles with at
+/// least `min_neighbours` of their own kind around them explode, so a thin
+/// trail just burns along.
+#[derive(Debug, Clone, Copy)]
+pub struct Explosion {
+    /// How far the blast reaches, in cells
+    pub radius: f32,
+    /// How much durability the blast can break through at its centre. It
+    /// falls off to nothing at the edge.
+    pub power: f32,
+    pub min_neighbours: u8,
+}
+
+/// The temperature of empty space, which everything cools (or warms) towards.
+pub const AMBIENT_TEMPERATURE: f32 = 20.0;
+
+/// An index into the material registry. The built-in types have constants
+/// here; anything else comes from a materials file at runtime.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ParticleType(pub(crate) u8);
 
+#[allow(non_upper_case_globals)]
 impl ParticleType {
-    pub const fn properties(&self) -> ParticleTypeProperties {
-        PROPERTIES[*self as usize]
+    pub const Border: ParticleType = ParticleType(0);
+    pub const Concrete: ParticleType = ParticleType(1);
+    pub const Empty: ParticleType = ParticleType(2);
+    pub const Sand: ParticleType = ParticleType(3);
+    pub const Water: ParticleType = ParticleType(4);
+    pub const Steam: ParticleType = ParticleType(5);
+    pub const Fungus: ParticleType = ParticleType(6);
+    pub const Flame: ParticleType = ParticleType(7);
+    pub const Methane: ParticleType = ParticleType(8);
+    pub const Gunpowder: ParticleType = ParticleType(9);
+    pub const Oil: ParticleType = ParticleType(10);
+    pub const Wood: ParticleType = ParticleType(11);
+    pub const Acid: ParticleType = ParticleType(12);
+    pub const Glass: ParticleType = ParticleType(13);
+    pub const Metal: ParticleType = ParticleType(14);
+    pub const Battery: ParticleType = ParticleType(15);
+    pub const Hydrogen: ParticleType = ParticleType(16);
+    pub const Lava: ParticleType = ParticleType(17);
+    pub const Stone: ParticleType = ParticleType(18);
+    pub const Obsidian: ParticleType = ParticleType(19);
+    pub const Ice: ParticleType = ParticleType(20);
+    pub const Snow: ParticleType = ParticleType(21);
+    pub const Salt: ParticleType = ParticleType(22);
+    pub const Brine: ParticleType = ParticleType(23);
+    pub const WeakAcid: ParticleType = ParticleType(24);
+    pub const Mud: ParticleType = ParticleType(25);
+    pub const Seed: ParticleType = ParticleType(26);
+    pub const Stem: ParticleType = ParticleType(27);
+    pub const Leaf: ParticleType = ParticleType(28);
+    pub const Root: ParticleType = ParticleType(29);
+    pub const Compost: ParticleType = ParticleType(30);
+
+    pub const BUILTIN_COUNT: usize = 31;
+}
+
+impl std::fmt::Debug for ParticleType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.properties().label)
     }
+}
 
-    fn premove_fn(&self) -> Box<dyn Fn(&mut Particle, I8Vec2, &mut WorldApi)> {
-        match self {
-            Self::Acid => Box::new(
-                |particle: &mut Particle, dxdy: I8Vec2, api: &mut WorldApi| {
-                    particle.try_decaying(dxdy, api);
-                },
-            ),
-            _ => Box::new(|_self: &mut Particle, _dxdy: I8Vec2, _api: &mut WorldApi| {}),
-        }
+// Serialized as a u32 so that save files line up with the ones written back
+// when this was a plain enum.
+impl Serialize for ParticleType {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u32(self.0 as u32)
+    }
+}
+
+impl<'de> Deserialize<'de> for ParticleType {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let index = u32::deserialize(deserializer)?;
+        u8::try_from(index)
+            .map(ParticleType)
+            .map_err(|_| serde::de::Error::custom("particle type index out of range"))
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+// Only kept for reading particles out of old world files. Particles now keep
+// track of being deleted in their flags.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 #[repr(u8)]
 enum Status {
     Deleted,
     Alive,
 }
 
-impl Status {
-    fn or(&self, other: Status) -> Status {
-        match (self, other) {
-            (Status::Alive, Status::Alive) => Status::Alive,
-            _ => Status::Deleted,
+/// One cell of the world. Kept small and `Copy` so that the grid is one flat
+/// block of memory and updating a particle doesn't allocate.
+///
+/// Whether the fields that only some particle types use (velocity and most
+/// of the flags) mean anything is looked up in the type's properties rather
+/// than stored per particle. Fuel, durability and charge, which fewer still
+/// use, are kept out of the way in a `ParticleExtras` alongside it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Particle {
+    pub particle_type: ParticleType,
+    flags: u8,
+    pub color: PColor,
+    original_color: PColor,
+    velocity: I8Vec2,
+    temperature: f32,
+}
+
+/// The rest of a particle, in a side table the world keeps next to the grid.
+/// It stays with its cell, so it's moved along with the particle whenever
+/// the particle moves, and starts over from the type's properties when a new
+/// particle is put there.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub(crate) struct ParticleExtras {
+    pub(crate) fuel: i16,
+    pub(crate) durability: i16,
+    // For conductors, how many more ticks it'll be sparking and then
+    // recovering for. 0 if it's ready to carry a spark.
+    pub(crate) charge: u8,
+}
+
+impl ParticleExtras {
+    pub(crate) fn new(particle_type: ParticleType) -> Self {
+        let properties = particle_type.properties();
+        Self {
+            fuel: properties.base_fuel.unwrap_or(0),
+            durability: properties.base_durability.unwrap_or(0),
+            charge: 0,
+        }
+    }
+}
+
+/// How particles are saved (world file versions 6 and 7): with their extras
+/// alongside everything else.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub(crate) struct SavedParticle {
+    pub(crate) particle_type: ParticleType,
+    flags: u8,
+    color: PColor,
+    original_color: PColor,
+    velocity: I8Vec2,
+    fuel: i16,
+    durability: i16,
+    temperature: f32,
+    charge: u8,
+}
+
+impl SavedParticle {
+    pub(crate) fn new(particle: Particle, extras: ParticleExtras) -> Self {
+        Self {
+            particle_type: particle.particle_type,
+            flags: particle.flags,
+            color: particle.color,
+            original_color: particle.original_color,
+            velocity: particle.velocity,
+            fuel: extras.fuel,
+            durability: extras.durability,
+            temperature: particle.temperature,
+            charge: extras.charge,
         }
     }
 
-    fn update(&mut self, other: Status) {
-        *self = self.or(other);
+    pub(crate) fn split(self) -> (Particle, ParticleExtras) {
+        let particle = Particle {
+            particle_type: self.particle_type,
+            flags: self.flags,
+            color: self.color,
+            original_color: self.original_color,
+            velocity: self.velocity,
+            temperature: self.temperature,
+        };
+        let extras = ParticleExtras {
+            fuel: self.fuel,
+            durability: self.durability,
+            charge: self.charge,
+        };
+        (particle, extras)
     }
 }
 
-// #[derive(Debug)]
-#[derive(Debug, Clone, PartialEq)]
-pub struct Particle {
-    pub particle_type: ParticleType,
-    pub updated: bool,
-    pub color: PColor,
+/// How particles were saved before they had a temperature (world file
+/// versions 1 and 2).
+#[derive(Deserialize)]
+pub(crate) struct ParticleV1 {
+    pub(crate) particle_type: ParticleType,
+    updated: bool,
+    color: PColor,
     original_color: PColor,
     status: Status,
     burning: bool,
     moved: Option<bool>,
     velocity: Option<I8Vec2>,
     moving_right: Option<bool>,
-    condensation_countdown: Option<i16>,
-    initial_condensation_countdown: Option<i16>,
+    _condensation_countdown: Option<i16>,
+    _initial_condensation_countdown: Option<i16>,
     watered: Option<bool>,
     fuel: Option<i16>,
     durability: Option<i16>,
 }
 
-// General Particle Methods
-impl Particle {
-    pub fn new(particle_type: ParticleType, rng: &mut ThreadRng) -> Self {
-        let (moved, velocity) = if particle_type.properties().moves {
-            (Some(false), Some(I8Vec2::ZERO))
-        } else {
-            (None, None)
-        };
+impl ParticleV1 {
+    pub(crate) fn into_particle(self, temperature: f32) -> ParticleV3 {
+        ParticleV3 {
+            particle_type: self.particle_type,
+            updated: self.updated,
+            color: self.color,
+            original_color: self.original_color,
+            status: self.status,
+            burning: self.burning,
+            moved: self.moved,
+            velocity: self.velocity,
+            moving_right: self.moving_right,
+            watered: self.watered,
+            fuel: self.fuel,
+            durability: self.durability,
+            temperature,
+        }
+    }
+}
 
-        let moving_right = if particle_type.properties().fluid {
-            Some(rng.gen())
-        } else {
-            None
-        };
+/// How particles were saved before they were packed (world file version 3).
+#[derive(Deserialize)]
+pub(crate) struct ParticleV3 {
+    pub(crate) particle_type: ParticleType,
+    updated: bool,
+    color: PColor,
+    original_color: PColor,
+    status: Status,
+    burning: bool,
+    moved: Option<bool>,
+    velocity: Option<I8Vec2>,
+    moving_right: Option<bool>,
+    watered: Option<bool>,
+    fuel: Option<i16>,
+    durability: Option<i16>,
+    temperature: f32,
+}
 
-        let condensation_countdown = if particle_type == ParticleType::Steam {
-            Some(300 + rng.gen_range(-100..100))
-        } else {
-            None
-        };
+impl ParticleV3 {
+    pub(crate) fn into_particle(self) -> ParticleV4 {
+        let flag = |set: bool, flag: u8| if set { flag } else { 0 };
+        ParticleV4 {
+            particle_type: self.particle_type,
+            flags: flag(self.updated, Particle::UPDATED)
+                | flag(self.status == Status::Deleted, Particle::DELETED)
+                | flag(self.burning, Particle::BURNING)
+                | flag(self.moved.unwrap_or(false), Particle::MOVED)
+                | flag(self.moving_right.unwrap_or(false), Particle::MOVING_RIGHT)
+                | flag(self.watered.unwrap_or(false), Particle::WATERED),
+            color: self.color,
+            original_color: self.original_color,
+            velocity: self.velocity.unwrap_or(I8Vec2::ZERO),
+            fuel: self.fuel.unwrap_or(0),
+            durability: self.durability.unwrap_or(0),
+            temperature: self.temperature,
+        }
+    }
+}
 
-        let watered = if particle_type == ParticleType::Fungus {
-            Some(false)
-        } else {
-            None
-        };
+/// How particles were saved before electricity (world file versions 4 and 5).
+#[derive(Deserialize)]
+pub(crate) struct ParticleV4 {
+    pub(crate) particle_type: ParticleType,
+    flags: u8,
+    color: PColor,
+    original_color: PColor,
+    velocity: I8Vec2,
+    fuel: i16,
+    durability: i16,
+    temperature: f32,
+}
+
+impl ParticleV4 {
+    pub(crate) fn into_particle(self) -> SavedParticle {
+        SavedParticle {
+            particle_type: self.particle_type,
+            flags: self.flags,
+            color: self.color,
+            original_color: self.original_color,
+            velocity: self.velocity,
+            fuel: self.fuel,
+            durability: self.durability,
+            temperature: self.temperature,
+            charge: 0,
+        }
+    }
+}
 
-        let burning = particle_type == ParticleType::Flame;
+// Neighbours, in the order particles look at them
+pub(crate) const ORTHOGONAL: [I8Vec2; 4] = [
+    I8Vec2::new(0, -1),
+    I8Vec2::new(1, 0),
+    I8Vec2::new(-1, 0),
+    I8Vec2::new(0, 1),
+];
+pub(crate) const ALL_AROUND: [I8Vec2; 8] = [
+    I8Vec2::new(0, -1),
+    I8Vec2::new(0, 1),
+    I8Vec2::new(1, 0),
+    I8Vec2::new(-1, 0),
+    I8Vec2::new(-1, -1),
+    I8Vec2::new(1, 1),
+    I8Vec2::new(1, -1),
+    I8Vec2::new(-1, 1),
+];
+
+// General Particle Methods
+impl Particle {
+    // Bits of `flags`
+    const UPDATED: u8 = 1;
+    const DELETED: u8 = 1 << 1;
+    const BURNING: u8 = 1 << 2;
+    const MOVED: u8 = 1 << 3;
+    const MOVING_RIGHT: u8 = 1 << 4;
+    const WATERED: u8 = 1 << 5;
+    // Made by Particle::new and not put in the world yet, so its cell's
+    // extras need starting over when it is
+    const FRESH: u8 = 1 << 6;
 
-        let fuel = particle_type.properties().base_fuel;
-        let durability = particle_type.properties().base_durability;
+    pub fn new<R: Rng>(particle_type: ParticleType, rng: &mut R) -> Self {
+        let properties = particle_type.properties();
 
         // let color = particle_type.properties().base_color;
         let color = if particle_type == ParticleType::Empty {
-            particle_type.properties().base_color
+            properties.base_color
         } else {
-            particle_type.properties().base_color.scale_hsv(
+            properties.base_color.scale_hsv(
                 0.0,
                 rng.gen_range(0.94..1.06),
                 rng.gen_range(0.98..1.02),
             )
         };
 
-        Self {
+        let mut particle = Self {
             particle_type,
-            updated: false,
+            flags: Particle::FRESH,
             color,
             original_color: color,
-            status: Status::Alive,
-            burning,
-            moved,
-            velocity,
-            moving_right,
-            condensation_countdown,
-            initial_condensation_countdown: condensation_countdown,
-            watered,
-            fuel,
-            durability,
+            velocity: I8Vec2::ZERO,
+            temperature: properties.initial_temperature,
+        };
+        if properties.fluid {
+            particle.set_flag(Particle::MOVING_RIGHT, rng.gen());
         }
+        particle.set_flag(Particle::BURNING, particle_type == ParticleType::Flame);
+        particle
+    }
+
+    fn flag(&self, flag: u8) -> bool {
+        self.flags & flag != 0
+    }
+
+    fn set_flag(&mut self, flag: u8, value: bool) {
+        if value {
+            self.flags |= flag;
+        } else {
+            self.flags &= !flag;
+        }
+    }
+
+    fn alive(&self) -> bool {
+        !self.flag(Particle::DELETED)
+    }
+
+    fn delete(&mut self) {
+        self.set_flag(Particle::DELETED, true);
+    }
+
+    fn has_fuel(&self) -> bool {
+        self.particle_type.properties().base_fuel.is_some()
+    }
+
+    fn has_durability(&self) -> bool {
+        self.particle_type.properties().base_durability.is_some()
     }
 
     pub fn update(&mut self, mut api: WorldApi) {
@@ -356,98 +419,190 @@ impl Particle {
             self.movement(&mut api);
         }
 
-        match self.particle_type {
-            ParticleType::Steam => {
-                let lasty = api.xy().1;
-                self.movement(&mut api);
-                self.update_condensation(&mut api, lasty);
-            }
-            ParticleType::Fungus => {
-                self.grow_fungus(&mut api);
-            }
-            ParticleType::Flame => {
-                if !self.burning {
-                    self.status = Status::Deleted;
-                    api.replace_with_new((0, 0), ParticleType::Empty);
-                }
-            }
-            _ => {}
+        if let Some(behavior) = self.particle_type.properties().behavior {
+            behavior.update(self, &mut api);
+        }
+
+        let properties = self.particle_type.properties();
+        if self.alive() && (properties.conductor || properties.battery) {
+            self.conduct_electricity(&mut api);
+        }
+
+        if self.alive() {
+            self.react(&mut api);
         }
 
-        if self.burning {
+        if self.is_burning() && self.alive() {
             self.burn(&mut api);
         }
 
-        if self.status == Status::Alive {
-            api.update_in_world(self.to_owned());
+        if self.alive() {
+            self.conduct_heat(&mut api);
+            self.change_phase(&mut api);
+        }
+
+        if self.alive() {
+            api.update_in_world(*self);
         }
     }
 
+    pub fn temperature(&self) -> f32 {
+        self.temperature
+    }
+
+    pub fn is_burning(&self) -> bool {
+        self.flag(Particle::BURNING)
+    }
+
+    pub(crate) fn updated(&self) -> bool {
+        self.flag(Particle::UPDATED)
+    }
+
+    pub(crate) fn set_updated(&mut self) {
+        self.set_flag(Particle::UPDATED, true);
+    }
+
     pub fn refresh(&mut self) {
-        self.updated = false;
-        if self.particle_type.properties().moves {
-            self.moved = Some(false);
+        self.set_flag(Particle::UPDATED, false);
+        self.set_flag(Particle::MOVED, false);
+    }
+
+    /// False once the particle has been replaced during this update.
+    pub fn is_alive(&self) -> bool {
+        self.alive()
+    }
+
+    /// Turn this particle into Empty. Meant for `ParticleBehavior`s; the
+    /// update stops here.
+    pub fn destroy(&mut self, api: &mut WorldApi) {
+        api.replace_with_new((0, 0), ParticleType::Empty);
+        self.delete();
+        if let Some(behavior) = self.particle_type.properties().behavior {
+            behavior.on_destroy(self, api);
         }
     }
+
+    pub fn set_temperature(&mut self, temperature: f32) {
+        self.temperature = temperature;
+    }
+
+    /// In cells per tick.
+    pub fn velocity(&self) -> I8Vec2 {
+        self.velocity
+    }
+
+    // Moving air carries light things along at its own speed, max_speed
+    // cells per tick towards dxdy. Heavier things only get speeded up by a
+    // cell per tick, some of the time, depending on `roll` (from 0 to 1).
+    pub(crate) fn blow(&mut self, dxdy: I8Vec2, max_speed: i8, roll: f32) {
+        let weight = self.particle_type.properties().weight;
+        if roll * weight > Particle::AIR_PUSH {
+            return;
+        }
+        let max_speed = max_speed.min(self.terminal_velocity());
+        let light = weight <= Particle::AIR_PUSH;
+        let blow = |velocity: &mut i8, d: i8| {
+            if d != 0 && *velocity * d < max_speed {
+                *velocity = if light { d * max_speed } else { *velocity + d };
+            }
+        };
+        blow(&mut self.velocity.x, dxdy.x);
+        blow(&mut self.velocity.y, dxdy.y);
+    }
+
+    // Capped at the particle's terminal velocity
+    pub(crate) fn set_velocity(&mut self, velocity: (f32, f32)) {
+        let max_speed = self.terminal_velocity() as f32;
+        let scale = (max_speed / velocity.0.hypot(velocity.1)).min(1.0);
+        self.velocity = i8vec2(
+            (velocity.0 * scale).round() as i8,
+            (velocity.1 * scale).round() as i8,
+        );
+    }
+
+    // Clears FRESH, and returns whether it was set
+    pub(crate) fn take_fresh(&mut self) -> bool {
+        let fresh = self.flag(Particle::FRESH);
+        self.set_flag(Particle::FRESH, false);
+        fresh
+    }
+}
+
+impl ParticleExtras {
+    // Returns true if that's worn it out. Things without durability never
+    // wear out.
+    pub(crate) fn wear(&mut self, particle_type: ParticleType, amount: i16) -> bool {
+        if particle_type.properties().base_durability.is_none() {
+            return false;
+        }
+        self.durability = self.durability.saturating_sub(amount);
+        self.durability < 0
+    }
 }
 
 /// Burning methods
 impl Particle {
-    fn set_burning(&mut self, b: bool) {
-        self.burning = b;
+    pub fn set_burning(&mut self, b: bool) {
+        self.set_flag(Particle::BURNING, b);
         if !b {
             self.color = self.original_color;
         }
     }
 
+    fn ignite(&mut self, api: &mut WorldApi) {
+        self.set_burning(true);
+        if let Some(behavior) = self.particle_type.properties().behavior {
+            behavior.on_ignite(self, api);
+        }
+    }
+
     fn burn(&mut self, api: &mut WorldApi) {
-        self.color = Particle::burning_flicker_color(api);
+        if let Some(explosion) = self.particle_type.properties().explodes {
+            let packed = ALL_AROUND
+                .iter()
+                .filter(|&&dxdy| api.neighbour(dxdy).particle_type == self.particle_type)
+                .count();
+            if packed >= explosion.min_neighbours as usize {
+                // The world sets it off once everything's updated, since the
+                // blast reaches further than a particle can
+                api.explode(explosion);
+                self.destroy(api);
+                return;
+            }
+        }
 
-        let dxdy_list = i8vec2_vector([(0, -1), (1, 0), (-1, 0), (0, 1)]);
+        self.color = Particle::burning_flicker_color(api);
+        // Fire spreads by heating its neighbours up past their ignition
+        // temperature, which conduct_heat takes care of
+        self.temperature = self
+            .temperature
+            .max(self.particle_type.properties().burn_temperature);
 
-        for dxdy in dxdy_list.into_iter() {
-            let r = api.random();
-            let neighbour = api.neighbour_mut(dxdy);
+        let mut extras = api.extras((0, 0));
+        for dxdy in ORTHOGONAL {
+            let neighbour = api.neighbour(dxdy);
 
-            // If our neighbour is watered (i.e. a watered fungus), we use its
-            // wet_flammability
-            let watered = neighbour.watered.unwrap_or(false);
-            let neighbour_flammability = if !watered {
-                neighbour.particle_type.properties().flammability
-            } else {
-                neighbour
-                    .particle_type
-                    .properties()
-                    .wet_flammability
-                    .unwrap()
-            };
-
-            if neighbour_flammability > 0.0 && !neighbour.burning {
-                if neighbour_flammability * (1.0 - 0.5 * dxdy.y as f32) > r {
-                    neighbour.set_burning(true);
-                }
-            } else if neighbour.particle_type == ParticleType::Empty && self.fuel.unwrap() > 0 {
-                if dxdy.y < 1 && api.neighbour((-1, 0)).burning && api.neighbour((1, 0)).burning {
-                    let mut new_flame = api.new_particle(ParticleType::Flame);
-                    new_flame.fuel = Some(api.random_range(0..self.fuel.unwrap()));
-                    api.replace_with(dxdy, new_flame);
-                }
-            } else if neighbour.particle_type == ParticleType::Water {
-                api.replace_with_new(dxdy, ParticleType::Steam);
-                self.set_burning(false);
-                break;
+            if neighbour.particle_type == ParticleType::Empty
+                && extras.fuel > 0
+                && dxdy.y < 1
+                && api.neighbour((-1, 0)).is_burning()
+                && api.neighbour((1, 0)).is_burning()
+            {
+                let new_flame = api.new_particle(ParticleType::Flame);
+                let mut flame_extras = ParticleExtras::new(ParticleType::Flame);
+                flame_extras.fuel = api.random_range(0..extras.fuel);
+                api.replace_with(dxdy, new_flame);
+                api.set_extras(dxdy, flame_extras);
             }
         }
 
-        if let Some(fuel) = self.fuel.as_mut() {
-            *fuel -= 1;
-            if *fuel < 0 {
-                api.replace_with_new((0, 0), ParticleType::Empty);
-                self.status.update(Status::Deleted);
-                return;
+        if self.has_fuel() {
+            extras.fuel -= 1;
+            api.set_extras((0, 0), extras);
+            if extras.fuel < 0 {
+                self.destroy(api);
             }
         }
-        self.status.update(Status::Alive);
     }
 
     fn burning_flicker_color(api: &mut WorldApi) -> PColor {
@@ -459,39 +614,183 @@ impl Particle {
     }
 }
 
+/// Reaction methods
+impl Particle {
+    fn react(&mut self, api: &mut WorldApi) {
+        for reaction in self.particle_type.reactions() {
+            if (reaction.while_burning && !self.is_burning())
+                || (reaction.while_dry && self.flag(Particle::WATERED))
+            {
+                continue;
+            }
+
+            for dxdy in ORTHOGONAL {
+                if !self.reacts_with(reaction, dxdy, api) {
+                    continue;
+                }
+                // Keep the chunk awake while there's something left to react with
+                api.might_update();
+                if api.random::<f32>() < reaction.probability {
+                    self.apply_reaction(reaction, dxdy, api);
+                    break;
+                }
+            }
+
+            if !self.alive() {
+                return;
+            }
+        }
+    }
+
+    fn reacts_with(&self, reaction: &Reaction, dxdy: I8Vec2, api: &WorldApi) -> bool {
+        let other = api.neighbour(dxdy);
+        let type_matches = match reaction.touching {
+            Some(touching) => other.particle_type == touching,
+            None => {
+                other.particle_type != ParticleType::Empty
+                    && other.particle_type != ParticleType::Border
+                    && other.particle_type != self.particle_type
+            }
+        };
+        type_matches
+            && (reaction.wear.is_none() || other.has_durability())
+            && (!reaction.touching_sparked || api.extras(dxdy).is_sparked())
+    }
+
+    fn apply_reaction(&mut self, reaction: &Reaction, dxdy: I8Vec2, api: &mut WorldApi) {
+        if let Some(wear) = reaction.wear {
+            let mut other = api.extras(dxdy);
+            other.durability -= wear;
+            api.set_extras(dxdy, other);
+            if other.durability < 0 {
+                api.replace_with_new(dxdy, ParticleType::Empty);
+            }
+            if self.has_durability() {
+                let mut extras = api.extras((0, 0));
+                extras.durability -= wear;
+                api.set_extras((0, 0), extras);
+                if extras.durability < 0 {
+                    self.destroy(api);
+                    return;
+                }
+            }
+        }
+
+        if let Some(touching_becomes) = reaction.touching_becomes {
+            api.replace_with_new(dxdy, touching_becomes);
+        }
+        if reaction.extinguishes {
+            self.set_burning(false);
+        }
+        if reaction.ignites && !self.is_burning() {
+            self.ignite(api);
+        }
+        if reaction.waters {
+            self.set_watered(true);
+        }
+
+        if let Some(cost) = reaction.fuel {
+            if self.has_fuel() {
+                let mut extras = api.extras((0, 0));
+                extras.fuel -= cost;
+                api.set_extras((0, 0), extras);
+                if extras.fuel < 0 {
+                    self.destroy(api);
+                    return;
+                }
+            }
+        }
+
+        match reaction.becomes {
+            Some(ParticleType::Empty) => self.destroy(api),
+            Some(becomes) => {
+                api.replace_with_new((0, 0), becomes);
+                self.delete();
+            }
+            None => {}
+        }
+    }
+}
+
+/// Electricity methods
+impl Particle {
+    // A conductor that's just been sparked carries it on to its neighbours at
+    // its next update, then needs RECOVERY more before it'll take another.
+    // Long enough that a spark can't bounce back the way it came, however
+    // the updates are ordered.
+    const RECOVERY: u8 = 4;
+    const JUST_SPARKED: u8 = Particle::RECOVERY + 2;
+    const SPARK_COLOR: PColor = PColor::new(255, 247, 168);
+
+    fn conduct_electricity(&mut self, api: &mut WorldApi) {
+        let properties = self.particle_type.properties();
+        let mut extras = api.extras((0, 0));
+        let sparking = properties.battery || extras.charge == Particle::RECOVERY + 1;
+        if sparking {
+            for dxdy in ORTHOGONAL {
+                let mut neighbour = api.extras(dxdy);
+                if api.neighbour(dxdy).particle_type.properties().conductor && neighbour.charge == 0
+                {
+                    neighbour.charge = Particle::JUST_SPARKED;
+                    api.set_extras(dxdy, neighbour);
+                }
+            }
+        }
+
+        // Batteries stay awake for as long as they've got something to power
+        if properties.battery
+            && ORTHOGONAL
+                .iter()
+                .any(|&dxdy| api.neighbour(dxdy).particle_type.properties().conductor)
+        {
+            api.might_update();
+        }
+
+        if extras.charge > 0 {
+            extras.charge -= 1;
+            api.set_extras((0, 0), extras);
+            self.color = if extras.is_sparked() {
+                Particle::SPARK_COLOR
+            } else {
+                self.original_color
+            };
+            api.might_update();
+        }
+    }
+}
+
+impl ParticleExtras {
+    pub(crate) fn is_sparked(&self) -> bool {
+        self.charge > Particle::RECOVERY
+    }
+}
+
 /// Fungus (plant?) methods
 impl Particle {
-    fn set_watered(&mut self, w: bool) {
+    pub fn is_watered(&self) -> bool {
+        self.flag(Particle::WATERED)
+    }
+
+    pub fn set_watered(&mut self, w: bool) {
         if w {
             self.color = self.original_color.scale_hsv(10.0, 1.7, 1.0);
         } else {
             self.color = self.original_color;
         }
-        self.watered = Some(w);
+        self.set_flag(Particle::WATERED, w);
     }
 
-    fn grow_fungus(&mut self, api: &mut WorldApi) {
-        let dxdy_list = i8vec2_vector([
-            (0, -1),
-            (0, 1),
-            (1, 0),
-            (-1, 0),
-            (-1, -1),
-            (1, 1),
-            (1, -1),
-            (-1, 1),
-        ]);
-
-        let dxdy = dxdy_list[api.random_range(0..dxdy_list.len())];
-        let mut neighbour_clone = api.neighbour(dxdy).clone();
-        if self.watered.unwrap() {
+    pub(crate) fn grow_fungus(&mut self, api: &mut WorldApi) {
+        let dxdy = ALL_AROUND[api.random_range(0..ALL_AROUND.len())];
+        let mut neighbour_clone = *api.neighbour(dxdy);
+        if self.flag(Particle::WATERED) {
             // This doesn't handle the edge case where every fungus particle is
             // watered and has no where to grow
             api.might_update();
 
             if neighbour_clone.particle_type == ParticleType::Empty {
                 let mut count = 0;
-                for ddxddy in dxdy_list {
+                for ddxddy in ALL_AROUND {
                     let dxdy2 = dxdy + ddxddy;
                     if api.neighbour(dxdy2).particle_type == ParticleType::Fungus {
                         count += 1;
@@ -504,145 +803,443 @@ impl Particle {
                     self.set_watered(false);
                 }
             } else if neighbour_clone.particle_type == ParticleType::Fungus
-                && !neighbour_clone.watered.unwrap()
+                && !neighbour_clone.flag(Particle::WATERED)
             {
                 neighbour_clone.set_watered(true);
                 api.replace_with(dxdy, neighbour_clone);
                 self.set_watered(false);
             }
-        } else if neighbour_clone.particle_type == ParticleType::Water {
-            api.replace_with_new(dxdy, ParticleType::Empty);
-            self.set_watered(true);
         }
     }
 }
 
-/// Condensation methods
+/// Plant methods
 impl Particle {
-    fn update_condensation(&mut self, api: &mut WorldApi, lasty: usize) {
-        if api.xy().1 == lasty {
-            if let Some(count) = self.condensation_countdown.as_mut() {
-                *count -= 1;
-                if *count <= 0 {
-                    api.replace_with_new((0, 0), ParticleType::Water);
-                    self.status.update(Status::Deleted);
-                    return;
+    // Chance per tick of a watered plant growing, if it has room to
+    const GROW_CHANCE: f32 = 0.05;
+    // Chance a stem puts out a leaf rather than growing taller, if it can
+    // do both
+    const LEAF_CHANCE: f32 = 0.3;
+    // Chance per tick of a starving plant losing a point of durability
+    const WILT_CHANCE: f32 = 0.01;
+
+    const SOIL: [ParticleType; 3] = [ParticleType::Sand, ParticleType::Mud, ParticleType::Compost];
+    const PLANT: [ParticleType; 3] = [ParticleType::Stem, ParticleType::Leaf, ParticleType::Root];
+
+    const UPWARDS: [I8Vec2; 3] = [I8Vec2::new(0, -1), I8Vec2::new(-1, -1), I8Vec2::new(1, -1)];
+    const DOWNWARDS: [I8Vec2; 3] = [I8Vec2::new(0, 1), I8Vec2::new(-1, 1), I8Vec2::new(1, 1)];
+    const SIDEWAYS: [I8Vec2; 2] = [I8Vec2::new(-1, 0), I8Vec2::new(1, 0)];
+    // Where water can go next, on its way from the roots to the leaves
+    const RISING: [I8Vec2; 5] = [
+        I8Vec2::new(0, -1),
+        I8Vec2::new(-1, -1),
+        I8Vec2::new(1, -1),
+        I8Vec2::new(-1, 0),
+        I8Vec2::new(1, 0),
+    ];
+
+    pub(crate) fn sprout(&mut self, api: &mut WorldApi) {
+        if !self.flag(Particle::WATERED)
+            || self.flag(Particle::MOVED)
+            || !Particle::SOIL.contains(&api.neighbour((0, 1)).particle_type)
+        {
+            return;
+        }
+        api.replace_with_new((0, 1), ParticleType::Root);
+        api.replace_with_new((0, 0), ParticleType::Stem);
+        self.delete();
+    }
+
+    pub(crate) fn grow_plant(&mut self, api: &mut WorldApi) {
+        if !self.flag(Particle::WATERED) {
+            // Stays awake until it gets some water or wilts away. It's only
+            // starving if there's no water anywhere near it in the plant.
+            api.might_update();
+            let starving = !ALL_AROUND.iter().any(|&dxdy| {
+                let neighbour = api.neighbour(dxdy);
+                Particle::PLANT.contains(&neighbour.particle_type)
+                    && neighbour.flag(Particle::WATERED)
+            });
+            if starving && api.random::<f32>() < Particle::WILT_CHANCE {
+                let mut extras = api.extras((0, 0));
+                let wilted = extras.wear(self.particle_type, 1);
+                api.set_extras((0, 0), extras);
+                if wilted {
+                    api.replace_with_new((0, 0), ParticleType::Compost);
+                    self.delete();
+                }
+            }
+            return;
+        }
+
+        // Anything with water gets over wilting
+        let base_durability = self.particle_type.properties().base_durability;
+        let mut extras = api.extras((0, 0));
+        if base_durability.is_some_and(|d| extras.durability < d) {
+            extras.durability += 1;
+            api.set_extras((0, 0), extras);
+            api.might_update();
+        }
+
+        if let Some((dxdy, grows)) = self.plant_growth(api) {
+            api.might_update();
+            if api.random::<f32>() < Particle::GROW_CHANCE {
+                api.replace_with_new(dxdy, grows);
+                self.set_watered(false);
+                return;
+            }
+        }
+
+        // Otherwise pass the water up to a dry part of the plant
+        let is_dry_plant =
+            |p: &Particle| Particle::PLANT.contains(&p.particle_type) && !p.flag(Particle::WATERED);
+        if !Particle::RISING
+            .iter()
+            .any(|&dxdy| is_dry_plant(api.neighbour(dxdy)))
+        {
+            return;
+        }
+        api.might_update();
+        let dxdy = Particle::RISING[api.random_range(0..Particle::RISING.len())];
+        let mut neighbour_clone = *api.neighbour(dxdy);
+        if is_dry_plant(&neighbour_clone) {
+            neighbour_clone.set_watered(true);
+            api.replace_with(dxdy, neighbour_clone);
+            self.set_watered(false);
+        }
+    }
+
+    // Where this part of a plant could grow next, and what it would grow
+    // there. Stems grow up towards open space and put out leaves to the
+    // sides; roots grow down into the soil.
+    fn plant_growth(&self, api: &mut WorldApi) -> Option<(I8Vec2, ParticleType)> {
+        let empty = [ParticleType::Empty];
+        match self.particle_type {
+            ParticleType::Stem => {
+                let stem =
+                    Particle::growth_direction(api, &Particle::UPWARDS, &empty, ParticleType::Stem)
+                        .map(|dxdy| (dxdy, ParticleType::Stem));
+                let leaf = Particle::leaf_direction(api).map(|dxdy| (dxdy, ParticleType::Leaf));
+                if api.random::<f32>() < Particle::LEAF_CHANCE {
+                    leaf.or(stem)
+                } else {
+                    stem.or(leaf)
                 }
             }
+            // Roots only go looking for more water once they've drunk what
+            // was around them
+            ParticleType::Root => {
+                let wet = [ParticleType::Water, ParticleType::Mud];
+                if ORTHOGONAL
+                    .iter()
+                    .any(|&dxdy| wet.contains(&api.neighbour(dxdy).particle_type))
+                {
+                    return None;
+                }
+                Particle::growth_direction(
+                    api,
+                    &Particle::DOWNWARDS,
+                    &Particle::SOIL,
+                    ParticleType::Root,
+                )
+                .map(|dxdy| (dxdy, ParticleType::Root))
+            }
+            _ => None,
+        }
+    }
+
+    // Whichever of `directions` leads into one of `into` with the most of
+    // `into` past it, as long as there's no other `grower` next to that spot,
+    // so plants grow in thin strands. Earlier directions win ties.
+    fn growth_direction(
+        api: &WorldApi,
+        directions: &[I8Vec2],
+        into: &[ParticleType],
+        grower: ParticleType,
+    ) -> Option<I8Vec2> {
+        directions
+            .iter()
+            .copied()
+            .filter(|&dxdy| into.contains(&api.neighbour(dxdy).particle_type))
+            .filter(|&dxdy| {
+                !ALL_AROUND.iter().any(|&ddxddy| {
+                    dxdy + ddxddy != I8Vec2::ZERO
+                        && api.neighbour(dxdy + ddxddy).particle_type == grower
+                })
+            })
+            .rev()
+            .max_by_key(|&dxdy| {
+                directions
+                    .iter()
+                    .filter(|&&ddxddy| into.contains(&api.neighbour(dxdy + ddxddy).particle_type))
+                    .count()
+            })
+    }
+
+    // An empty space beside a stem with no other leaves next to it
+    fn leaf_direction(api: &WorldApi) -> Option<I8Vec2> {
+        Particle::SIDEWAYS.into_iter().find(|&dxdy| {
+            api.neighbour(dxdy).particle_type == ParticleType::Empty
+                && !ALL_AROUND
+                    .iter()
+                    .any(|&ddxddy| api.neighbour(dxdy + ddxddy).particle_type == ParticleType::Leaf)
+        })
+    }
+}
+
+/// Heat methods
+impl Particle {
+    // Neighbours closer in temperature than this don't bother exchanging heat,
+    // so that things settle down and their chunks can go to sleep
+    const HEAT_EPSILON: f32 = 0.5;
+
+    fn ignition_temperature(&self) -> Option<f32> {
+        let properties = self.particle_type.properties();
+        if self.flag(Particle::WATERED) {
+            properties.wet_ignition_temperature
         } else {
-            self.condensation_countdown = self.initial_condensation_countdown;
+            properties.ignition_temperature
+        }
+    }
+
+    fn conduct_heat(&mut self, api: &mut WorldApi) {
+        let properties = self.particle_type.properties();
+        for dxdy in ORTHOGONAL {
+            let neighbour = api.neighbour(dxdy);
+            if neighbour.particle_type == ParticleType::Border {
+                continue;
+            }
+            // Empty space is air at a constant temperature, so it soaks up (or
+            // gives out) as much heat as it needs to
+            let is_air = neighbour.particle_type == ParticleType::Empty;
+            let neighbour_temperature = if is_air {
+                AMBIENT_TEMPERATURE
+            } else {
+                neighbour.temperature
+            };
+
+            let difference = neighbour_temperature - self.temperature;
+            if difference.abs() < Particle::HEAT_EPSILON {
+                continue;
+            }
+
+            let neighbour_properties = neighbour.particle_type.properties();
+            let conductivity = properties
+                .conductivity
+                .min(neighbour_properties.conductivity);
+            let heat = 0.2 * conductivity * difference;
+            self.temperature += heat / properties.heat_capacity;
+            if !is_air {
+                api.neighbour_mut(dxdy).temperature -= heat / neighbour_properties.heat_capacity;
+            }
+            api.might_update();
+        }
+
+        let hot_enough = self
+            .ignition_temperature()
+            .is_some_and(|t| self.temperature >= t);
+        if !self.is_burning() && hot_enough {
+            self.ignite(api);
         }
-        self.status.update(Status::Alive);
+    }
+
+    fn change_phase(&mut self, api: &mut WorldApi) {
+        let properties = self.particle_type.properties();
+        let becomes = match (properties.heated, properties.cooled) {
+            (Some(heated), _) if self.temperature >= heated.temperature => heated.becomes,
+            (_, Some(cooled)) if self.temperature < cooled.temperature => cooled.becomes,
+            _ => return,
+        };
+
+        let mut new_particle = api.new_particle(becomes);
+        new_particle.temperature = self.temperature;
+        api.replace_with((0, 0), new_particle);
+        self.delete();
     }
 }
 
 /// Movement Methods
 impl Particle {
+    // Fluids landing at least this fast splash sideways
+    const SPLASH_SPEED: i8 = 3;
+    // Things this light or lighter always get pushed by wind and fans
+    const AIR_PUSH: f32 = 10.0;
+
     fn rises(&self) -> bool {
         self.particle_type.properties().weight < ParticleType::Empty.properties().weight
     }
 
     fn movement(&mut self, api: &mut WorldApi) {
-        if self.moved.unwrap() {
+        if self.flag(Particle::MOVED) {
             return;
         }
 
-        let check_directions;
+        let wind = api.wind();
+        if wind != 0 {
+            let roll = api.random::<f32>();
+            self.blow(i8vec2(wind.signum(), 0), wind.abs(), roll);
+        }
 
         // Apply gravity to things that don't rise
-        if !self.rises() {
-            if let Some(vel) = self.velocity.as_mut() {
-                let mag_v_sq = vel.length_sq();
-                if mag_v_sq
-                    < self
-                        .particle_type
-                        .properties()
-                        .terminal_velocity_sq
-                        .unwrap()
-                {
-                    vel.y += 1;
-                }
-            }
+        let terminal_velocity = self.terminal_velocity();
+        if !self.rises() && self.velocity.y < terminal_velocity {
+            self.velocity.y += 1;
         }
 
-        let last_dir;
+        // Carry on the way it's already going, if it can. Otherwise it falls,
+        // rolls or flows the way it always has.
+        if !self.move_with_velocity(api) {
+            self.settle(api);
+        }
 
-        if self.particle_type.properties().fluid {
-            check_directions = if self.moving_right.unwrap() {
-                i8vec2_vector([(0, 1), (1, 1), (-1, 1), (1, 0), (-1, 0)])
+        let resting = if self.rises() {
+            true
+        } else {
+            api.neighbour((0, 1)).particle_type != ParticleType::Empty
+        };
+        if resting {
+            // Friction, or air resistance for gases
+            self.velocity.x -= self.velocity.x.signum();
+            if self.rises() {
+                self.velocity.y -= self.velocity.y.signum();
+            }
+        }
+    }
+
+    // The direction lists for things that couldn't carry on moving the way
+    // they were going
+    fn settle(&mut self, api: &mut WorldApi) {
+        let properties = self.particle_type.properties();
+        if properties.fluid
+            && properties.viscosity > 0.0
+            && api.random::<f32>() < properties.viscosity
+        {
+            // Too thick to flow this tick, so all it can do is fall
+            api.might_update();
+            self.movement_loop(api, &[I8Vec2::new(0, 1)]);
+        } else if properties.fluid {
+            let moving_right = self.flag(Particle::MOVING_RIGHT);
+            let check_directions = if moving_right {
+                [(0, 1), (1, 1), (-1, 1), (1, 0), (-1, 0)]
             } else {
-                i8vec2_vector([(0, 1), (-1, 1), (1, 1), (-1, 0), (1, 0)])
-            };
+                [(0, 1), (-1, 1), (1, 1), (-1, 0), (1, 0)]
+            }
+            .map(I8Vec2::from);
 
-            let last_possible_dir = check_directions[4].clone();
+            let last_possible_dir = check_directions[4];
 
-            last_dir = self.movement_loop(api, check_directions);
+            let last_dir = self.movement_loop(api, &check_directions);
 
             if let Some(last_dxdy) = last_dir {
                 if last_dxdy == (-1, 1).into() {
-                    self.moving_right = Some(false)
+                    self.set_flag(Particle::MOVING_RIGHT, false);
                 }
                 if last_dxdy == (1, 1).into() {
-                    self.moving_right = Some(true)
+                    self.set_flag(Particle::MOVING_RIGHT, true);
                 } else if last_dxdy == last_possible_dir {
-                    self.moving_right = Some(!self.moving_right.unwrap());
+                    self.set_flag(Particle::MOVING_RIGHT, !moving_right);
                 }
             }
         } else {
             let r = api.random::<bool>();
             let right = if r { -1 } else { 1 };
-            check_directions = i8vec2_vector([(0, 1), (right, 1), (0 - right, 1)]);
-            last_dir = self.movement_loop(api, check_directions);
-        }
-
-        let last_dxdy = last_dir.unwrap_or(i8vec2(1, 0));
-
-        // Reset vertical velocity if we've stopped (could maybe just slow down instead?)
-        if last_dxdy.x != 0 {
-            if let Some(vel) = self.velocity.as_mut() {
-                vel.y = 0;
-            }
+            let check_directions = [(0, 1), (right, 1), (0 - right, 1)].map(I8Vec2::from);
+            self.movement_loop(api, &check_directions);
         }
     }
 
-    fn movement_loop(
-        &mut self,
-        api: &mut WorldApi,
-        check_directions: Vec<I8Vec2>,
-    ) -> Option<I8Vec2> {
+    fn movement_loop(&mut self, api: &mut WorldApi, check_directions: &[I8Vec2]) -> Option<I8Vec2> {
         //
         let dispersion_rate = self.particle_type.properties().dispersion_rate.unwrap_or(1) as i8;
-        for dir in check_directions.into_iter() {
+        for &dir in check_directions {
             //
             let r = if self.rises() { -1 } else { 1 };
 
             let dxdy = if dir.y == 0 {
                 i8vec2(dispersion_rate * dir.x, dir.y)
-            } else if r == 1 && dir.x == 0 {
-                i8vec2(dir.x, self.velocity.unwrap().y)
             } else {
                 i8vec2(dir.x, r * dir.y)
             };
 
             if dxdy.x.abs() > 1 {
                 self.disperse(dxdy, api);
-            } else if dxdy.y > 1 {
-                self.fall_with_gravity(dxdy, api);
             } else {
                 self.try_moving_to(dxdy, api);
             }
-            if self.moved.unwrap() {
+            if self.flag(Particle::MOVED) {
                 return Some(dir);
             }
         }
         None
     }
 
-    fn fall_with_gravity(&mut self, dxdy: I8Vec2, api: &mut WorldApi) {
-        iterate_over_line_delta(dxdy.into(), |dx, dy| {
-            self.try_moving_to(i8vec2(dx as i8, dy as i8), api);
-            self.moved.unwrap()
-        })
+    fn terminal_velocity(&self) -> i8 {
+        let terminal_velocity_sq = self.particle_type.properties().terminal_velocity_sq;
+        (terminal_velocity_sq.unwrap_or(1) as f32).sqrt() as i8
+    }
+
+    // Moves along the particle's velocity until it hits something, and
+    // returns whether it got anywhere
+    fn move_with_velocity(&mut self, api: &mut WorldApi) -> bool {
+        if self.velocity == I8Vec2::ZERO {
+            return false;
+        }
+
+        let mut moved = false;
+        let mut blocked = None;
+        let against_gravity = if self.rises() { 1 } else { -1 };
+        iterate_over_line_delta(self.velocity.into(), |dx, dy| {
+            let dxdy = i8vec2(dx as i8, dy as i8);
+            // Only thrown upwards through empty space, not through whatever
+            // it would normally sink into
+            let free = dxdy.y != against_gravity
+                || api.neighbour(dxdy).particle_type == ParticleType::Empty;
+            if free && self.try_moving_to(dxdy, api).is_some() {
+                moved = true;
+                true
+            } else {
+                blocked = Some(dxdy);
+                false
+            }
+        });
+
+        if let Some(dxdy) = blocked {
+            self.collide(dxdy, api);
+        }
+        moved
+    }
+
+    // Loses the speed going into whatever's in the way, and fluids splash
+    // sideways if they hit it hard enough
+    fn collide(&mut self, dxdy: I8Vec2, api: &mut WorldApi) {
+        let hit_y =
+            dxdy.y != 0 && api.neighbour(i8vec2(0, dxdy.y)).particle_type != ParticleType::Empty;
+        let hit_x =
+            dxdy.x != 0 && api.neighbour(i8vec2(dxdy.x, 0)).particle_type != ParticleType::Empty;
+
+        if hit_y || !hit_x {
+            let other = api.neighbour(i8vec2(0, dxdy.y));
+            if other.particle_type.properties().moves
+                && other.velocity.y.signum() == self.velocity.y.signum()
+            {
+                // Caught up with something falling (or rising) more slowly
+                self.velocity.y = other.velocity.y;
+            } else {
+                let impact = self.velocity.y.abs();
+                self.velocity.y = 0;
+                if self.particle_type.properties().fluid && impact >= Particle::SPLASH_SPEED {
+                    let side = if api.random::<bool>() { 1 } else { -1 };
+                    self.velocity.x += side * impact / 2;
+                }
+            }
+        }
+        if hit_x {
+            // Bounce off walls
+            self.velocity.x = -self.velocity.x / 2;
+        }
+        let terminal_velocity = self.terminal_velocity();
+        self.velocity.x = self.velocity.x.clamp(-terminal_velocity, terminal_velocity);
+        self.velocity.y = self.velocity.y.clamp(-terminal_velocity, terminal_velocity);
     }
 
     fn disperse(&mut self, dxdy: I8Vec2, api: &mut WorldApi) {
@@ -665,33 +1262,13 @@ impl Particle {
         })
     }
 
-    fn try_decaying(&mut self, dxdy: I8Vec2, api: &mut WorldApi) {
-        let other_p = api.neighbour_mut(dxdy);
-        if other_p.particle_type != self.particle_type {
-            if let Some(other_durability) = other_p.durability.as_mut() {
-                *other_durability -= 1;
-                if let Some(my_durability) = self.durability.as_mut() {
-                    *my_durability -= 1;
-                }
-                if *other_durability < 0 {
-                    api.replace_with_new(dxdy, ParticleType::Empty);
-                }
-                if self.durability.unwrap() < 0 {
-                    api.replace_with_new((0, 0), ParticleType::Empty);
-                    self.status.update(Status::Deleted);
-                }
-            }
-        }
-        self.status.update(Status::Alive);
-    }
-
     /// Checks if this particle can and will move in the given direction.
-    /// Assumes that if it can move there it will (sets self.moved to true)
+    /// Assumes that if it can move there it will (sets the MOVED flag)
     fn try_moving_to(&mut self, dxdy: I8Vec2, api: &mut WorldApi) -> Option<ParticleType> {
-        let premove_fn = self.particle_type.premove_fn();
-        premove_fn(self, dxdy, api);
-        if self.status == Status::Deleted {
-            return None;
+        if let Some(behavior) = self.particle_type.properties().behavior {
+            if !behavior.premove(self, dxdy, api) {
+                return None;
+            }
         }
 
         let other_p = api.neighbour(dxdy);
@@ -705,17 +1282,28 @@ impl Particle {
         if other_type == ParticleType::Empty {
             // If we're moving sideways don't compare weights, just do it
             if dxdy.y == 0 || Particle::weight_check(api, rises, my_weight, other_weight) {
-                self.moved = Some(true);
+                self.set_flag(Particle::MOVED, true);
                 api.swap_with(dxdy);
                 return Some(other_type);
             }
-        } else if other_p.particle_type.properties().moves && !other_p.moved.unwrap() {
+        } else if other_p.particle_type.properties().moves && !other_p.flag(Particle::MOVED) {
             // If there's something there and it's moveable and it hasn't
             // already moved, then we might swap with it
             if Particle::weight_check(api, rises, my_weight, other_weight) {
+                // Pushing it out of the way slows this down, and passes on
+                // some of the sideways speed
+                let share = other_weight / (my_weight + other_weight);
+                let velocity = self.velocity;
+                self.velocity = i8vec2(
+                    (velocity.x as f32 * (1.0 - share)).round() as i8,
+                    (velocity.y as f32 * (1.0 - share)).round() as i8,
+                );
                 let other_p_mut = api.neighbour_mut(dxdy);
-                other_p_mut.moved = Some(true);
-                self.moved = Some(true);
+                let pushed = (velocity.x as f32 * share).round() as i8;
+                let terminal_velocity = other_p_mut.terminal_velocity();
+                other_p_mut.velocity.x = pushed.clamp(-terminal_velocity, terminal_velocity);
+                other_p_mut.set_flag(Particle::MOVED, true);
+                self.set_flag(Particle::MOVED, true);
                 api.swap_with(dxdy);
                 return Some(other_type);
             }
@@ -0,0 +1,287 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+// In hundredths of a second, the smallest delay most viewers will respect
+const GIF_FRAME_DELAY: u16 = 2;
+
+#[derive(Debug)]
+pub enum RecordError {
+    Io(std::io::Error),
+    Png(png::EncodingError),
+    Gif(gif::EncodingError),
+    TooLarge,
+    WrongFrameSize { expected: usize, got: usize },
+}
+
+impl std::fmt::Display for RecordError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecordError::Io(e) => write!(f, "{}", e),
+            RecordError::Png(e) => write!(f, "could not write png: {}", e),
+            RecordError::Gif(e) => write!(f, "could not write gif: {}", e),
+            RecordError::TooLarge => write!(f, "recording is too large for a gif"),
+            RecordError::WrongFrameSize { expected, got } => {
+                write!(f, "frame is {} bytes, expected {}", got, expected)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RecordError {}
+
+impl From<std::io::Error> for RecordError {
+    fn from(value: std::io::Error) -> Self {
+        RecordError::Io(value)
+    }
+}
+
+impl From<png::EncodingError> for RecordError {
+    fn from(value: png::EncodingError) -> Self {
+        RecordError::Png(value)
+    }
+}
+
+impl From<gif::EncodingError> for RecordError {
+    fn from(value: gif::EncodingError) -> Self {
+        RecordError::Gif(value)
+    }
+}
+
+enum Output {
+    PngFrames(PathBuf),
+    Gif(gif::Encoder<BufWriter<File>>),
+}
+
+/// Writes a sequence of RGBA frames (one pixel per particle, like `Frame` or
+/// the app's screen buffer) out as numbered PNGs or an animated GIF, scaled
+/// up by a whole number of pixels per particle.
+pub struct Recorder {
+    output: Output,
+    width: usize,
+    height: usize,
+    scale: usize,
+    frames_written: usize,
+}
+
+impl std::fmt::Debug for Recorder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Recorder")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("scale", &self.scale)
+            .field("frames_written", &self.frames_written)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Recorder {
+    /// Record to `frame_00000.png`, `frame_00001.png`, ... in `dir`, creating
+    /// it if needed.
+    pub fn png_frames<P: AsRef<Path>>(
+        dir: P,
+        width: usize,
+        height: usize,
+        scale: usize,
+    ) -> Result<Self, RecordError> {
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self::with_output(
+            Output::PngFrames(dir.as_ref().to_owned()),
+            width,
+            height,
+            scale,
+        ))
+    }
+
+    /// Record to a looping animated GIF at `path`.
+    pub fn gif<P: AsRef<Path>>(
+        path: P,
+        width: usize,
+        height: usize,
+        scale: usize,
+    ) -> Result<Self, RecordError> {
+        let scale = scale.max(1);
+        let gif_width = u16::try_from(width * scale).map_err(|_| RecordError::TooLarge)?;
+        let gif_height = u16::try_from(height * scale).map_err(|_| RecordError::TooLarge)?;
+
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = gif::Encoder::new(file, gif_width, gif_height, &[])?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+
+        Ok(Self::with_output(
+            Output::Gif(encoder),
+            width,
+            height,
+            scale,
+        ))
+    }
+
+    /// Pick the format from the path: a `.gif` file, or otherwise a directory
+    /// of PNG frames.
+    pub fn to_path<P: AsRef<Path>>(
+        path: P,
+        width: usize,
+        height: usize,
+        scale: usize,
+    ) -> Result<Self, RecordError> {
+        let is_gif = path
+            .as_ref()
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("gif"));
+        if is_gif {
+            Recorder::gif(path, width, height, scale)
+        } else {
+            Recorder::png_frames(path, width, height, scale)
+        }
+    }
+
+    fn with_output(output: Output, width: usize, height: usize, scale: usize) -> Self {
+        Self {
+            output,
+            width,
+            height,
+            scale: scale.max(1),
+            frames_written: 0,
+        }
+    }
+
+    pub fn frames_written(&self) -> usize {
+        self.frames_written
+    }
+
+    /// Add a frame. `pixels` must be `width * height` RGBA pixels.
+    pub fn record(&mut self, pixels: &[u8]) -> Result<(), RecordError> {
+        let expected = 4 * self.width * self.height;
+        if pixels.len() != expected {
+            return Err(RecordError::WrongFrameSize {
+                expected,
+                got: pixels.len(),
+            });
+        }
+        let mut scaled = upscale(pixels, self.width, self.height, self.scale);
+        let width = self.width * self.scale;
+        let height = self.height * self.scale;
+
+        match &mut self.output {
+            Output::PngFrames(dir) => {
+                let path = dir.join(format!("frame_{:05}.png", self.frames_written));
+                let mut encoder = png::Encoder::new(
+                    BufWriter::new(File::create(path)?),
+                    width as u32,
+                    height as u32,
+                );
+                encoder.set_color(png::ColorType::Rgba);
+                encoder.set_depth(png::BitDepth::Eight);
+                encoder.write_header()?.write_image_data(&scaled)?;
+            }
+            Output::Gif(encoder) => {
+                let mut frame =
+                    gif::Frame::from_rgba_speed(width as u16, height as u16, &mut scaled, 10);
+                frame.delay = GIF_FRAME_DELAY;
+                encoder.write_frame(&frame)?;
+            }
+        }
+
+        self.frames_written += 1;
+        Ok(())
+    }
+
+    /// Finish writing. GIFs aren't valid until this has been called.
+    pub fn finish(self) -> Result<(), RecordError> {
+        if let Output::Gif(encoder) = self.output {
+            encoder
+                .into_inner()?
+                .into_inner()
+                .map_err(|e| e.into_error())?;
+        }
+        Ok(())
+    }
+}
+
+fn upscale(pixels: &[u8], width: usize, height: usize, scale: usize) -> Vec<u8> {
+    if scale == 1 {
+        return pixels.to_vec();
+    }
+
+    let mut scaled = Vec::with_capacity(pixels.len() * scale * scale);
+    for y in 0..height {
+        let row = &pixels[4 * y * width..4 * (y + 1) * width];
+        let start = scaled.len();
+        for pixel in row.chunks_exact(4) {
+            for _ in 0..scale {
+                scaled.extend_from_slice(pixel);
+            }
+        }
+        let end = scaled.len();
+        for _ in 1..scale {
+            scaled.extend_from_within(start..end);
+        }
+    }
+    scaled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Two 3x2 frames, one red and one blue
+    fn record_two_frames(path: &Path) {
+        let mut recorder = Recorder::to_path(path, 3, 2, 2).unwrap();
+        assert!(matches!(
+            recorder.record(&[0; 4]),
+            Err(RecordError::WrongFrameSize {
+                expected: 24,
+                got: 4
+            })
+        ));
+        recorder.record(&[255, 0, 0, 255].repeat(6)).unwrap();
+        recorder.record(&[0, 0, 255, 255].repeat(6)).unwrap();
+        assert_eq!(recorder.frames_written(), 2);
+        recorder.finish().unwrap();
+    }
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sand-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn writes_png_frames() {
+        let dir = scratch_dir("png-frames");
+        record_two_frames(&dir);
+
+        for (i, color) in [[255, 0, 0, 255], [0, 0, 255, 255]].iter().enumerate() {
+            let file = File::open(dir.join(format!("frame_{:05}.png", i))).unwrap();
+            let mut reader = png::Decoder::new(std::io::BufReader::new(file))
+                .read_info()
+                .unwrap();
+            let mut pixels = vec![0; reader.output_buffer_size()];
+            let info = reader.next_frame(&mut pixels).unwrap();
+            assert_eq!((info.width, info.height), (6, 4));
+            assert_eq!(pixels, color.repeat(24));
+        }
+        assert!(!dir.join("frame_00002.png").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn writes_a_gif() {
+        let dir = scratch_dir("gif");
+        let path = dir.join("recording.gif");
+        record_two_frames(&path);
+
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::RGBA);
+        let mut decoder = options.read_info(File::open(&path).unwrap()).unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (6, 4));
+        let mut colors = vec![];
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            assert_eq!(frame.delay, GIF_FRAME_DELAY);
+            colors.push(frame.buffer[..4].to_vec());
+        }
+        assert_eq!(colors, [[255, 0, 0, 255], [0, 0, 255, 255]]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,898 @@
+use super::*;
+use serde::Deserialize;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// The built-in particle types, which ship in this file. They're always the
+/// first entries in the registry, in the same order as the `ParticleType`
+/// constants.
+const DEFAULT_MATERIALS: &str = include_str!("../materials.toml");
+
+const BUILTIN_LABELS: [&str; ParticleType::BUILTIN_COUNT] = [
+    "Border",
+    "Concrete",
+    "Empty",
+    "Sand",
+    "Water",
+    "Steam",
+    "Fungus",
+    "Flame",
+    "Methane",
+    "Gunpowder",
+    "Oil",
+    "Wood",
+    "Acid",
+    "Glass",
+    "Metal",
+    "Battery",
+    "Hydrogen",
+    "Lava",
+    "Stone",
+    "Obsidian",
+    "Ice",
+    "Snow",
+    "Salt",
+    "Brine",
+    "Weak Acid",
+    "Mud",
+    "Seed",
+    "Stem",
+    "Leaf",
+    "Root",
+    "Compost",
+];
+
+static REGISTRY: OnceLock<MaterialRegistry> = OnceLock::new();
+
+#[derive(Debug)]
+pub enum MaterialError {
+    Io(std::io::Error),
+    Parse(toml::de::Error),
+    Invalid { label: String, message: String },
+    TooMany,
+    AlreadyLoaded,
+    Script { name: String, message: String },
+}
+
+impl std::fmt::Display for MaterialError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MaterialError::Io(e) => write!(f, "{}", e),
+            MaterialError::Parse(e) => write!(f, "{}", e),
+            MaterialError::Invalid { label, message } => write!(f, "{}: {}", label, message),
+            MaterialError::TooMany => write!(f, "too many particle types (at most 256)"),
+            MaterialError::AlreadyLoaded => {
+                write!(f, "particle types have already been loaded or used")
+            }
+            MaterialError::Script { name, message } => write!(f, "{}: {}", name, message),
+        }
+    }
+}
+
+impl std::error::Error for MaterialError {}
+
+impl From<std::io::Error> for MaterialError {
+    fn from(value: std::io::Error) -> Self {
+        MaterialError::Io(value)
+    }
+}
+
+impl From<toml::de::Error> for MaterialError {
+    fn from(value: toml::de::Error) -> Self {
+        MaterialError::Parse(value)
+    }
+}
+
+// What a single [[material]] in the file looks like
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MaterialDefinition {
+    label: String,
+    color: String,
+    weight: f32,
+    #[serde(default)]
+    moves: bool,
+    #[serde(default)]
+    auto_move: bool,
+    #[serde(default)]
+    fluid: bool,
+    terminal_velocity: Option<u8>,
+    dispersion_rate: Option<u8>,
+    #[serde(default)]
+    viscosity: f32,
+    fuel: Option<i16>,
+    durability: Option<i16>,
+    #[serde(default)]
+    conductor: bool,
+    #[serde(default)]
+    battery: bool,
+    #[serde(default = "default_conductivity")]
+    conductivity: f32,
+    #[serde(default = "default_heat_capacity")]
+    heat_capacity: f32,
+    #[serde(default = "default_temperature")]
+    temperature: f32,
+    ignition_temperature: Option<f32>,
+    wet_ignition_temperature: Option<f32>,
+    #[serde(default = "default_burn_temperature")]
+    burn_temperature: f32,
+    heated: Option<HeatedDefinition>,
+    cooled: Option<CooledDefinition>,
+    shatters_into: Option<String>,
+    explodes: Option<ExplosionDefinition>,
+    behavior: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HeatedDefinition {
+    above: f32,
+    becomes: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CooledDefinition {
+    below: f32,
+    becomes: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ExplosionDefinition {
+    radius: f32,
+    power: f32,
+    #[serde(default)]
+    min_neighbours: u8,
+}
+
+fn default_conductivity() -> f32 {
+    0.1
+}
+
+fn default_heat_capacity() -> f32 {
+    1.0
+}
+
+fn default_temperature() -> f32 {
+    AMBIENT_TEMPERATURE
+}
+
+fn default_burn_temperature() -> f32 {
+    800.0
+}
+
+// What a single [[reaction]] in the file looks like
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ReactionDefinition {
+    reactant: String,
+    touching: String,
+    #[serde(default = "always")]
+    probability: f32,
+    becomes: Option<String>,
+    touching_becomes: Option<String>,
+    #[serde(default)]
+    while_burning: bool,
+    #[serde(default)]
+    while_dry: bool,
+    #[serde(default)]
+    touching_sparked: bool,
+    #[serde(default)]
+    extinguishes: bool,
+    #[serde(default)]
+    ignites: bool,
+    #[serde(default)]
+    waters: bool,
+    wear: Option<i16>,
+    fuel: Option<i16>,
+}
+
+fn always() -> f32 {
+    1.0
+}
+
+#[derive(Debug, Deserialize)]
+struct MaterialFile {
+    #[serde(default)]
+    material: Vec<MaterialDefinition>,
+    #[serde(default)]
+    reaction: Vec<ReactionDefinition>,
+}
+
+impl MaterialDefinition {
+    // Phase changes and shatters_into can name materials further down the
+    // file, so they're left for add_from_str to fill in once everything is
+    // registered
+    fn into_properties(self) -> Result<ParticleTypeProperties, MaterialError> {
+        let invalid = |message: &str| MaterialError::Invalid {
+            label: self.label.clone(),
+            message: message.to_owned(),
+        };
+
+        let base_color =
+            PColor::from_hex(&self.color).ok_or_else(|| invalid("color should be #rrggbb"))?;
+        if self.weight.is_nan() {
+            return Err(invalid("weight should be a number"));
+        }
+        if self.auto_move && !self.moves {
+            return Err(invalid("auto_move needs moves"));
+        }
+        if self.moves && self.terminal_velocity.is_none() {
+            return Err(invalid("things that move need a terminal_velocity"));
+        }
+        if self.fluid && self.dispersion_rate.is_none() {
+            return Err(invalid("fluids need a dispersion_rate"));
+        }
+        // It's used as an i8 when moving
+        if self
+            .dispersion_rate
+            .is_some_and(|d| !(1..=127).contains(&d))
+        {
+            return Err(invalid("dispersion_rate should be between 1 and 127"));
+        }
+        if !(0.0..1.0).contains(&self.viscosity) {
+            return Err(invalid("viscosity should be at least 0 and less than 1"));
+        }
+        if self.ignition_temperature.is_some() && self.fuel.is_none() {
+            return Err(invalid("flammable things need fuel"));
+        }
+        if self.wet_ignition_temperature.is_some() && self.ignition_temperature.is_none() {
+            return Err(invalid(
+                "wet_ignition_temperature needs ignition_temperature",
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.conductivity) {
+            return Err(invalid("conductivity should be between 0 and 1"));
+        }
+        if self.heat_capacity < 1.0 {
+            return Err(invalid("heat_capacity should be at least 1"));
+        }
+        if let Some(explodes) = &self.explodes {
+            if !(1.0..=32.0).contains(&explodes.radius) {
+                return Err(invalid("explosion radius should be between 1 and 32"));
+            }
+            if explodes.power <= 0.0 {
+                return Err(invalid("explosion power should be more than 0"));
+            }
+            if self.fuel.is_none() {
+                return Err(invalid("explosives need fuel"));
+            }
+        }
+        let behavior = match &self.behavior {
+            Some(name) => Some(
+                find_behavior(name)
+                    .ok_or_else(|| invalid(&format!("unknown behavior {}", name)))?,
+            ),
+            None => None,
+        };
+
+        Ok(ParticleTypeProperties {
+            label: self.label,
+            base_color,
+            weight: self.weight,
+            moves: self.moves,
+            auto_move: self.auto_move,
+            fluid: self.fluid,
+            terminal_velocity_sq: self.terminal_velocity.map(|v| u16::pow(v as u16, 2)),
+            dispersion_rate: self.dispersion_rate,
+            viscosity: self.viscosity,
+            base_fuel: self.fuel,
+            base_durability: self.durability,
+            conductor: self.conductor,
+            battery: self.battery,
+            conductivity: self.conductivity,
+            heat_capacity: self.heat_capacity,
+            initial_temperature: self.temperature,
+            ignition_temperature: self.ignition_temperature,
+            wet_ignition_temperature: self.wet_ignition_temperature,
+            burn_temperature: self.burn_temperature,
+            heated: None,
+            cooled: None,
+            shatters_into: None,
+            explodes: self.explodes.map(|e| Explosion {
+                radius: e.radius,
+                power: e.power,
+                min_neighbours: e.min_neighbours,
+            }),
+            behavior,
+        })
+    }
+}
+
+// The rest of the world is built on Empty being nothing and Border never
+// moving, so overriding them can only change how they look and hold heat
+fn check_fixed_override(
+    properties: &ParticleTypeProperties,
+    old: &ParticleTypeProperties,
+    phases: &(
+        Option<HeatedDefinition>,
+        Option<CooledDefinition>,
+        Option<String>,
+    ),
+) -> Result<(), MaterialError> {
+    let unchanged = properties.weight == old.weight
+        && !properties.moves
+        && !properties.fluid
+        && properties.dispersion_rate.is_none()
+        && properties.base_fuel.is_none()
+        && properties.base_durability.is_none()
+        && !properties.conductor
+        && !properties.battery
+        && properties.ignition_temperature.is_none()
+        && properties.explodes.is_none()
+        && properties.behavior.is_none()
+        && phases.0.is_none()
+        && phases.1.is_none()
+        && phases.2.is_none();
+    if unchanged {
+        Ok(())
+    } else {
+        Err(MaterialError::Invalid {
+            label: old.label.clone(),
+            message: "only color, conductivity, heat_capacity and temperature can be changed"
+                .to_owned(),
+        })
+    }
+}
+
+impl ReactionDefinition {
+    fn into_reaction(self, registry: &MaterialRegistry) -> Result<Reaction, MaterialError> {
+        let name = format!("{} + {}", self.reactant, self.touching);
+        let invalid = |message: String| MaterialError::Invalid {
+            label: name.clone(),
+            message,
+        };
+        let find = |label: &str| {
+            registry
+                .find(label)
+                .ok_or_else(|| invalid(format!("unknown particle type {}", label)))
+        };
+        // "any" matches every particle type, apart from the ones noted on Reaction
+        let find_or_any = |label: &str| match label {
+            "any" => Ok(None),
+            _ => find(label).map(Some),
+        };
+
+        if !(0.0..=1.0).contains(&self.probability) {
+            return Err(invalid("probability should be between 0 and 1".to_owned()));
+        }
+
+        Ok(Reaction {
+            reactant: find_or_any(&self.reactant)?,
+            touching: find_or_any(&self.touching)?,
+            probability: self.probability,
+            becomes: self.becomes.as_deref().map(find).transpose()?,
+            touching_becomes: self.touching_becomes.as_deref().map(find).transpose()?,
+            while_burning: self.while_burning,
+            while_dry: self.while_dry,
+            touching_sparked: self.touching_sparked,
+            extinguishes: self.extinguishes,
+            ignites: self.ignites,
+            waters: self.waters,
+            wear: self.wear,
+            fuel: self.fuel,
+        })
+    }
+}
+
+/// "A touching B (with some probability) becomes C and D". Every tick, each
+/// particle checks its reactions against its four direct neighbours, and the
+/// first neighbour that a reaction applies to takes part in it.
+#[derive(Debug, Clone)]
+pub struct Reaction {
+    /// `None` reacts for every particle type
+    pub reactant: Option<ParticleType>,
+    /// `None` reacts with anything except Empty, Border and the reactant's
+    /// own type
+    pub touching: Option<ParticleType>,
+    pub probability: f32,
+    pub becomes: Option<ParticleType>,
+    pub touching_becomes: Option<ParticleType>,
+    /// Only reacts while the reactant is on fire
+    pub while_burning: bool,
+    /// Only reacts while the reactant isn't watered
+    pub while_dry: bool,
+    /// Only reacts with a conductor that's carrying a spark
+    pub touching_sparked: bool,
+    /// Puts out the reactant
+    pub extinguishes: bool,
+    /// Sets the reactant on fire
+    pub ignites: bool,
+    /// Waters the reactant
+    pub waters: bool,
+    /// Durability both particles lose. Only reacts with things that have
+    /// durability, and whichever runs out turns to Empty.
+    pub wear: Option<i16>,
+    /// Fuel the reactant uses up, turning to Empty if it runs out
+    pub fuel: Option<i16>,
+}
+
+/// Every particle type the simulation knows about, indexed by `ParticleType`,
+/// along with the reactions between them.
+#[derive(Debug)]
+pub struct MaterialRegistry {
+    properties: Vec<ParticleTypeProperties>,
+    reactions: Vec<Reaction>,
+    // The reactions that apply to each particle type, in file order
+    reactions_by_type: Vec<Vec<Reaction>>,
+    // Whether any material runs a behaviour from register_behavior, which
+    // could reach anywhere in the world
+    registered_behaviors: bool,
+}
+
+impl MaterialRegistry {
+    fn builtin() -> Self {
+        let mut registry = Self {
+            properties: vec![],
+            reactions: vec![],
+            reactions_by_type: vec![],
+            registered_behaviors: false,
+        };
+        registry
+            .add_from_str(DEFAULT_MATERIALS)
+            .expect("built-in materials.toml is invalid");
+
+        for (properties, label) in registry.properties.iter().zip(BUILTIN_LABELS) {
+            assert_eq!(
+                properties.label, label,
+                "built-in materials are out of order"
+            );
+        }
+        assert_eq!(registry.properties.len(), ParticleType::BUILTIN_COUNT);
+
+        registry
+    }
+
+    /// Materials with the same label as an existing one replace its
+    /// properties; anything else becomes a new particle type.
+    fn add_from_str(&mut self, text: &str) -> Result<(), MaterialError> {
+        let file: MaterialFile = toml::from_str(text)?;
+
+        let mut phase_changes = vec![];
+        for mut definition in file.material {
+            let phases = (
+                definition.heated.take(),
+                definition.cooled.take(),
+                definition.shatters_into.take(),
+            );
+            let mut properties = definition.into_properties()?;
+            if matches!(properties.behavior, Some(Behavior::Registered(_))) {
+                self.registered_behaviors = true;
+            }
+            let particle_type = match self.find(&properties.label) {
+                Some(existing @ (ParticleType::Empty | ParticleType::Border)) => {
+                    let old = &self.properties[existing.0 as usize];
+                    check_fixed_override(&properties, old, &phases)?;
+                    self.properties[existing.0 as usize] = properties;
+                    existing
+                }
+                Some(existing) => {
+                    // Retuning a material shouldn't lose the code behind it
+                    let old = &self.properties[existing.0 as usize];
+                    properties.behavior = properties.behavior.or(old.behavior);
+                    self.properties[existing.0 as usize] = properties;
+                    existing
+                }
+                None if self.properties.len() < 256 => {
+                    self.properties.push(properties);
+                    ParticleType((self.properties.len() - 1) as u8)
+                }
+                None => return Err(MaterialError::TooMany),
+            };
+            phase_changes.push((particle_type, phases));
+        }
+
+        for (particle_type, (heated, cooled, shatters_into)) in phase_changes {
+            let find = |label: &str| {
+                self.find(label).ok_or_else(|| MaterialError::Invalid {
+                    label: self.properties[particle_type.0 as usize].label.clone(),
+                    message: format!("unknown particle type {}", label),
+                })
+            };
+            let heated = match heated {
+                Some(h) => Some(PhaseChange {
+                    temperature: h.above,
+                    becomes: find(&h.becomes)?,
+                }),
+                None => None,
+            };
+            let cooled = match cooled {
+                Some(c) => Some(PhaseChange {
+                    temperature: c.below,
+                    becomes: find(&c.becomes)?,
+                }),
+                None => None,
+            };
+            let shatters_into = shatters_into.as_deref().map(find).transpose()?;
+            let properties = &mut self.properties[particle_type.0 as usize];
+            properties.heated = heated;
+            properties.cooled = cooled;
+            properties.shatters_into = shatters_into;
+        }
+
+        // Reactions come after the materials so they can refer to any of them
+        let reactions = file
+            .reaction
+            .into_iter()
+            .map(|definition| definition.into_reaction(self))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.merge_reactions(reactions);
+        self.index_reactions();
+        Ok(())
+    }
+
+    // Reactions between the same reactant and touching as earlier ones take
+    // their place (all of them, if there were several), so that loading a
+    // copy of the built-in file doesn't make everything react twice as often.
+    // The rest go on the end.
+    fn merge_reactions(&mut self, reactions: Vec<Reaction>) {
+        let same_pair =
+            |a: &Reaction, b: &Reaction| a.reactant == b.reactant && a.touching == b.touching;
+        let mut placed = vec![false; reactions.len()];
+        let mut merged = Vec::with_capacity(self.reactions.len() + reactions.len());
+        for old in std::mem::take(&mut self.reactions) {
+            let mut replaced = false;
+            for (i, reaction) in reactions.iter().enumerate() {
+                if same_pair(reaction, &old) {
+                    replaced = true;
+                    if !placed[i] {
+                        placed[i] = true;
+                        merged.push(reaction.clone());
+                    }
+                }
+            }
+            if !replaced {
+                merged.push(old);
+            }
+        }
+        merged.extend(
+            reactions
+                .into_iter()
+                .zip(placed)
+                .filter(|(_, placed)| !placed)
+                .map(|(reaction, _)| reaction),
+        );
+        self.reactions = merged;
+    }
+
+    fn index_reactions(&mut self) {
+        self.reactions_by_type = ParticleType::all_in(self.properties.len())
+            .map(|particle_type| {
+                self.reactions
+                    .iter()
+                    .filter(|r| r.reactant.is_none_or(|reactant| reactant == particle_type))
+                    .cloned()
+                    .collect()
+            })
+            .collect();
+    }
+
+    fn find(&self, label: &str) -> Option<ParticleType> {
+        self.properties
+            .iter()
+            .position(|p| p.label.eq_ignore_ascii_case(label))
+            .map(|i| ParticleType(i as u8))
+    }
+
+    pub fn len(&self) -> usize {
+        self.properties.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.properties.is_empty()
+    }
+}
+
+pub(crate) fn registry() -> &'static MaterialRegistry {
+    REGISTRY.get_or_init(MaterialRegistry::builtin)
+}
+
+pub(crate) fn registry_loaded() -> bool {
+    REGISTRY.get().is_some()
+}
+
+pub(crate) fn uses_registered_behaviors() -> bool {
+    registry().registered_behaviors
+}
+
+/// Load extra or retuned particle types on top of the built-in ones. Has to
+/// happen before anything asks about a particle type, since after that the
+/// registry can't change.
+pub fn load_materials<P: AsRef<Path>>(path: P) -> Result<(), MaterialError> {
+    load_materials_from_str(&std::fs::read_to_string(path)?)
+}
+
+pub fn load_materials_from_str(text: &str) -> Result<(), MaterialError> {
+    let mut registry = MaterialRegistry::builtin();
+    // The app loads materials.toml from the working directory, which is
+    // usually just this same file
+    if text != DEFAULT_MATERIALS {
+        registry.add_from_str(text)?;
+    }
+    REGISTRY
+        .set(registry)
+        .map_err(|_| MaterialError::AlreadyLoaded)
+}
+
+impl ParticleType {
+    pub fn properties(&self) -> &'static ParticleTypeProperties {
+        &registry().properties[self.0 as usize]
+    }
+
+    /// Look up a particle type by its label, ignoring case.
+    pub fn from_label(label: &str) -> Option<ParticleType> {
+        registry().find(label)
+    }
+
+    /// Every registered particle type, built-in ones first.
+    pub fn all() -> impl Iterator<Item = ParticleType> {
+        ParticleType::all_in(registry().len())
+    }
+
+    fn all_in(count: usize) -> impl Iterator<Item = ParticleType> {
+        (0..count).map(|i| ParticleType(i as u8))
+    }
+
+    /// The reactions this particle type takes part in as the reactant.
+    pub fn reactions(&self) -> &'static [Reaction] {
+        &registry().reactions_by_type[self.0 as usize]
+    }
+
+    pub fn is_builtin(&self) -> bool {
+        (self.0 as usize) < ParticleType::BUILTIN_COUNT
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with(text: &str) -> Result<MaterialRegistry, MaterialError> {
+        let mut registry = MaterialRegistry::builtin();
+        registry.add_from_str(text)?;
+        Ok(registry)
+    }
+
+    fn properties<'a>(registry: &'a MaterialRegistry, label: &str) -> &'a ParticleTypeProperties {
+        &registry.properties[registry.find(label).unwrap().0 as usize]
+    }
+
+    fn invalid_message(text: &str) -> String {
+        match with(text) {
+            Err(MaterialError::Invalid { message, .. }) => message,
+            other => panic!("expected an invalid material, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn builtin_file_loads_in_order() {
+        let registry = MaterialRegistry::builtin();
+        assert_eq!(registry.len(), ParticleType::BUILTIN_COUNT);
+        assert_eq!(registry.find("sand"), Some(ParticleType::Sand));
+        assert_eq!(registry.find("Weak Acid"), Some(ParticleType::WeakAcid));
+
+        // Loading the same file on top changes nothing
+        let again = with(DEFAULT_MATERIALS).unwrap();
+        assert_eq!(again.len(), registry.len());
+        assert_eq!(again.reactions.len(), registry.reactions.len());
+    }
+
+    #[test]
+    fn new_material_gets_defaults() {
+        let registry = with(
+            r##"
+            [[material]]
+            label = "Slime"
+            color = "#40ff40"
+            weight = 1.5
+            moves = true
+            auto_move = true
+            fluid = true
+            terminal_velocity = 2
+            dispersion_rate = 1
+            heated = { above = 300.0, becomes = "Dried Slime" }
+
+            [[material]]
+            label = "Dried Slime"
+            color = "#208020"
+            weight = inf
+            "##,
+        )
+        .unwrap();
+        assert_eq!(registry.len(), ParticleType::BUILTIN_COUNT + 2);
+        assert_eq!(
+            registry.find("Slime"),
+            Some(ParticleType(ParticleType::BUILTIN_COUNT as u8))
+        );
+
+        let slime = properties(&registry, "Slime");
+        assert_eq!(slime.base_color, PColor::new(0x40, 0xff, 0x40));
+        assert_eq!(slime.terminal_velocity_sq, Some(4));
+        assert_eq!(slime.conductivity, 0.1);
+        assert_eq!(slime.heat_capacity, 1.0);
+        assert_eq!(slime.initial_temperature, AMBIENT_TEMPERATURE);
+        assert_eq!(slime.base_fuel, None);
+        assert!(slime.behavior.is_none());
+        // Named before it was defined
+        assert_eq!(
+            slime.heated.map(|h| h.becomes),
+            registry.find("Dried Slime")
+        );
+        assert!(properties(&registry, "Dried Slime").weight.is_infinite());
+    }
+
+    #[test]
+    fn override_retunes_builtin() {
+        let registry = with(
+            r##"
+            [[material]]
+            label = "fungus"
+            color = "#ff00ff"
+            weight = inf
+            fuel = 10
+            ignition_temperature = 100.0
+            "##,
+        )
+        .unwrap();
+        assert_eq!(registry.len(), ParticleType::BUILTIN_COUNT);
+        let fungus = properties(&registry, "Fungus");
+        assert_eq!(fungus.base_color, PColor::new(0xff, 0x00, 0xff));
+        assert_eq!(fungus.base_fuel, Some(10));
+        // Keeps its behaviour without naming it again
+        assert!(matches!(fungus.behavior, Some(Behavior::Fungus)));
+        assert!(!registry.registered_behaviors);
+    }
+
+    #[test]
+    fn bad_definitions_are_rejected() {
+        let material = |extra: &str| {
+            format!(
+                "[[material]]\nlabel = \"Thing\"\ncolor = \"#123456\"\nweight = 1.0\n{}",
+                extra
+            )
+        };
+        assert!(matches!(
+            with(&material("sparkly = true")),
+            Err(MaterialError::Parse(_))
+        ));
+        assert_eq!(
+            invalid_message(&material("auto_move = true")),
+            "auto_move needs moves"
+        );
+        assert_eq!(
+            invalid_message(&material("ignition_temperature = 100.0")),
+            "flammable things need fuel"
+        );
+        assert_eq!(
+            invalid_message(&material("behavior = \"nothing\"")),
+            "unknown behavior nothing"
+        );
+        assert_eq!(
+            invalid_message(&material("cooled = { below = 0.0, becomes = \"Nope\" }")),
+            "unknown particle type Nope"
+        );
+        assert_eq!(
+            invalid_message("[[material]]\nlabel = \"Thing\"\ncolor = \"blue\"\nweight = 1.0"),
+            "color should be #rrggbb"
+        );
+        assert_eq!(
+            invalid_message("[[material]]\nlabel = \"Thing\"\ncolor = \"#123456\"\nweight = nan"),
+            "weight should be a number"
+        );
+        assert_eq!(
+            invalid_message(&material("fluid = true\ndispersion_rate = 200")),
+            "dispersion_rate should be between 1 and 127"
+        );
+    }
+
+    #[test]
+    fn empty_and_border_only_change_looks_and_heat() {
+        let fixed = |label: &str, weight: &str, extra: &str| {
+            format!(
+                "[[material]]\nlabel = \"{}\"\ncolor = \"#000000\"\nweight = {}\n{}",
+                label, weight, extra
+            )
+        };
+        let registry = with(&fixed("Empty", "1.0", "conductivity = 0.5")).unwrap();
+        let empty = properties(&registry, "Empty");
+        assert_eq!(empty.base_color, PColor::new(0, 0, 0));
+        assert_eq!(empty.conductivity, 0.5);
+        assert!(with(&fixed("Border", "inf", "temperature = 100.0")).is_ok());
+
+        let rejected = [
+            fixed("Empty", "5.0", ""),
+            fixed("Empty", "1.0", "fuel = 10\nignition_temperature = 100.0"),
+            fixed("Empty", "1.0", "behavior = \"fungus\""),
+            fixed("Border", "inf", "moves = true\nterminal_velocity = 5"),
+            fixed("Border", "inf", "shatters_into = \"Sand\""),
+        ];
+        for text in rejected {
+            assert_eq!(
+                invalid_message(&text),
+                "only color, conductivity, heat_capacity and temperature can be changed",
+                "{}",
+                text
+            );
+        }
+    }
+
+    fn position(registry: &MaterialRegistry, reactant: &str, touching: &str) -> Option<usize> {
+        registry.reactions.iter().position(|r| {
+            r.reactant == registry.find(reactant) && r.touching == registry.find(touching)
+        })
+    }
+
+    #[test]
+    fn reactions_replace_the_same_pair_in_place() {
+        let builtin = MaterialRegistry::builtin();
+        let acid_water = position(&builtin, "Acid", "Water").unwrap();
+
+        let registry = with(
+            r#"
+            [[reaction]]
+            reactant = "Glass"
+            touching = "Lava"
+            probability = 0.5
+            becomes = "Sand"
+            touching_becomes = "Stone"
+
+            [[reaction]]
+            reactant = "Acid"
+            touching = "Water"
+            becomes = "Water"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(registry.reactions.len(), builtin.reactions.len() + 1);
+
+        let replaced = &registry.reactions[acid_water];
+        assert_eq!(position(&registry, "Acid", "Water"), Some(acid_water));
+        assert_eq!(replaced.probability, 1.0);
+        assert_eq!(replaced.becomes, Some(ParticleType::Water));
+
+        let added = registry.reactions.last().unwrap();
+        assert_eq!(added.reactant, Some(ParticleType::Glass));
+        assert_eq!(added.touching, Some(ParticleType::Lava));
+        assert_eq!(added.probability, 0.5);
+        assert_eq!(added.becomes, Some(ParticleType::Sand));
+        assert_eq!(added.touching_becomes, Some(ParticleType::Stone));
+        assert!(registry.reactions_by_type[ParticleType::Glass.0 as usize]
+            .iter()
+            .any(|r| r.touching == Some(ParticleType::Lava)));
+    }
+
+    #[test]
+    fn any_reactant_applies_to_everything() {
+        let registry = MaterialRegistry::builtin();
+        let any_water = &registry.reactions[position(&registry, "any", "Water").unwrap()];
+        assert_eq!(any_water.reactant, None);
+        assert!(any_water.while_burning);
+        for reactions in &registry.reactions_by_type {
+            assert!(reactions
+                .iter()
+                .any(|r| r.reactant.is_none() && r.touching == Some(ParticleType::Water)));
+        }
+        // Acid's own reactions come along with the ones for everything
+        let acid = &registry.reactions_by_type[ParticleType::Acid.0 as usize];
+        assert!(acid.iter().any(|r| r.reactant == Some(ParticleType::Acid)));
+        assert!(!acid.iter().any(|r| r.reactant == Some(ParticleType::Salt)));
+    }
+
+    #[test]
+    fn bad_reactions_are_rejected() {
+        let reaction = |extra: &str| format!("[[reaction]]\nreactant = \"Sand\"\n{}", extra);
+        assert!(matches!(
+            with(&reaction("probability = 1.0")),
+            Err(MaterialError::Parse(_))
+        ));
+        assert_eq!(
+            invalid_message(&reaction("touching = \"Water\"\nprobability = 2.0")),
+            "probability should be between 0 and 1"
+        );
+        assert_eq!(
+            invalid_message(&reaction("touching = \"Custard\"")),
+            "unknown particle type Custard"
+        );
+    }
+}
@@ -0,0 +1,25 @@
+use super::*;
+
+/// Everything the simulation needs to put itself on screen. `World` only
+/// talks to this trait, so the core has no idea what is actually drawing it.
+pub trait Renderer {
+    /// Write a single particle's color into the screen buffer. Called for
+    /// every particle in a chunk that changed this frame.
+    fn update_image_with_particle(&mut self, x: usize, y: usize, width: usize, color: PColor);
+
+    /// Present the screen buffer, once all particles have been written to it.
+    fn draw_screen(&mut self, world_width: u16, world_height: u16);
+
+    /// Outline a chunk that will be updated next frame.
+    fn debug_chunk(&self, x: usize, y: usize, width: usize, height: usize, text: &str);
+
+    /// Draw a source (or a sink, if `particle_type` is `Empty`) over the world.
+    fn draw_source(&self, x: usize, y: usize, particle_type: ParticleType, replaces: bool);
+
+    /// Draw one cell of a portal on the edge it faces.
+    fn draw_portal(&self, x: usize, y: usize, direction: Direction, color: PColor);
+
+    /// Draw a fan blowing towards `direction`, with a strength from 1 to
+    /// `MAX_FAN_STRENGTH`.
+    fn draw_fan(&self, x: usize, y: usize, direction: Direction, strength: u8);
+}
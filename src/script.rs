@@ -0,0 +1,384 @@
+//! Behaviours written as [Rhai](https://rhai.rs) scripts, for materials that
+//! need more than the materials file can describe but shouldn't need a
+//! rebuild. Only there with the `scripting` feature.
+//!
+//! A script defines whichever of these hooks it needs, each of which gets the
+//! particle's `api`:
+//!
+//! ```text
+//! fn update(api) { ... }
+//! fn premove(api, dx, dy) { ... }   // false stops the move
+//! fn on_ignite(api) { ... }
+//! fn on_destroy(api) { ... }
+//! ```
+//!
+//! `api` has `neighbour(dx, dy)` (the label of the particle there),
+//! `swap_with(dx, dy)`, `replace_with_new(dx, dy, label)`, `random()` (from 0
+//! to 1) and `might_update()`, which do the same as on `WorldApi`, except that
+//! scripts only reach the eight cells around the particle and can't swap
+//! into the border. It only works during the hook it was handed to. The
+//! particle itself is written back after `update`, so replacing `(0, 0)` only
+//! sticks from `on_destroy`.
+//!
+//! A hook that fails part way through (reaching too far, a label that doesn't
+//! exist, or running for too long) stops there, and the first error each
+//! script hits is kept for `take_script_errors`. `reload_scripts` picks up
+//! edits to the files without restarting.
+
+use super::*;
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, Scope, AST};
+use std::cell::Cell;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, RwLock};
+
+// Enough for anything that looks at its neighbourhood, while stopping a
+// script stuck in a loop from hanging the world
+const MAX_OPERATIONS: u64 = 100_000;
+
+/// A `ParticleBehavior` that runs a script's hooks. Register it like any
+/// other behaviour, or use `register_script_behaviors` for a whole directory.
+#[derive(Debug)]
+pub struct ScriptBehavior {
+    name: String,
+    // Where it was loaded from, if it can be reloaded
+    path: Option<PathBuf>,
+    engine: Engine,
+    script: RwLock<Script>,
+    error: Mutex<ScriptError>,
+}
+
+#[derive(Debug)]
+struct Script {
+    ast: AST,
+    has_update: bool,
+    has_premove: bool,
+    has_on_ignite: bool,
+    has_on_destroy: bool,
+}
+
+#[derive(Debug, Default)]
+struct ScriptError {
+    first: Option<String>,
+    reported: bool,
+}
+
+impl ScriptBehavior {
+    /// Compile a script. `name` is used to say which script an error came
+    /// from.
+    pub fn new(name: &str, source: &str) -> Result<Self, MaterialError> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine
+            .register_type_with_name::<ScriptApi>("WorldApi")
+            .register_fn("neighbour", ScriptApi::neighbour)
+            .register_fn("swap_with", ScriptApi::swap_with)
+            .register_fn("replace_with_new", ScriptApi::replace_with_new)
+            .register_fn("random", ScriptApi::random)
+            .register_fn("might_update", ScriptApi::might_update);
+        let script = Script::compile(&engine, name, source)?;
+        Ok(ScriptBehavior {
+            name: name.to_owned(),
+            path: None,
+            engine,
+            script: RwLock::new(script),
+            error: Mutex::new(ScriptError::default()),
+        })
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, MaterialError> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)?;
+        let mut behavior = ScriptBehavior::new(&path.display().to_string(), &source)?;
+        behavior.path = Some(path.to_owned());
+        Ok(behavior)
+    }
+
+    /// Compile the file it came from again. If that fails, the script that was
+    /// running carries on.
+    pub fn reload(&self) -> Result<(), MaterialError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let source = std::fs::read_to_string(path)?;
+        let script = Script::compile(&self.engine, &self.name, &source)?;
+        *self.script.write().unwrap() = script;
+        *self.error.lock().unwrap() = ScriptError::default();
+        Ok(())
+    }
+
+    /// The first error the script hit while running, if it has.
+    pub fn error(&self) -> Option<MaterialError> {
+        let error = self.error.lock().unwrap();
+        error.first.as_ref().map(|message| MaterialError::Script {
+            name: self.name.clone(),
+            message: message.clone(),
+        })
+    }
+
+    fn call<T: Clone + Send + Sync + 'static>(
+        &self,
+        hook: &str,
+        api: &mut WorldApi,
+        args: Vec<Dynamic>,
+    ) -> Option<T> {
+        let script = self.script.read().unwrap();
+        let call = ApiCall::enter(api);
+        let mut call_args = vec![Dynamic::from(call.handle())];
+        call_args.extend(args);
+        // Nothing at the top level of the script needs running again
+        let options = CallFnOptions::new().eval_ast(false);
+        let result = self.engine.call_fn_with_options(
+            options,
+            &mut Scope::new(),
+            &script.ast,
+            hook,
+            call_args,
+        );
+        drop(call);
+        result
+            .map_err(|e| {
+                let mut error = self.error.lock().unwrap();
+                error
+                    .first
+                    .get_or_insert_with(|| format!("in {}: {}", hook, e));
+            })
+            .ok()
+    }
+}
+
+impl Script {
+    fn compile(engine: &Engine, name: &str, source: &str) -> Result<Self, MaterialError> {
+        let ast = engine.compile(source).map_err(|e| MaterialError::Script {
+            name: name.to_owned(),
+            message: e.to_string(),
+        })?;
+        let has = |hook: &str, params: usize| {
+            ast.iter_functions()
+                .any(|f| f.name == hook && f.params.len() == params)
+        };
+        Ok(Script {
+            has_update: has("update", 1),
+            has_premove: has("premove", 3),
+            has_on_ignite: has("on_ignite", 1),
+            has_on_destroy: has("on_destroy", 1),
+            ast,
+        })
+    }
+}
+
+impl ParticleBehavior for ScriptBehavior {
+    fn update(&self, _particle: &mut Particle, api: &mut WorldApi) {
+        if self.script.read().unwrap().has_update {
+            self.call::<Dynamic>("update", api, vec![]);
+        }
+    }
+
+    fn premove(&self, _particle: &mut Particle, dxdy: I8Vec2, api: &mut WorldApi) -> bool {
+        if !self.script.read().unwrap().has_premove {
+            return true;
+        }
+        let args = vec![Dynamic::from(dxdy.x as i64), Dynamic::from(dxdy.y as i64)];
+        self.call::<bool>("premove", api, args).unwrap_or(true)
+    }
+
+    fn on_ignite(&self, _particle: &mut Particle, api: &mut WorldApi) {
+        if self.script.read().unwrap().has_on_ignite {
+            self.call::<Dynamic>("on_ignite", api, vec![]);
+        }
+    }
+
+    fn on_destroy(&self, _particle: &Particle, api: &mut WorldApi) {
+        if self.script.read().unwrap().has_on_destroy {
+            self.call::<Dynamic>("on_destroy", api, vec![]);
+        }
+    }
+}
+
+// ─── Registration ────────────────────────────────────────────────────────────
+
+static SCRIPTS: Mutex<Vec<&'static ScriptBehavior>> = Mutex::new(vec![]);
+
+/// Register every `.rhai` file in `dir` as a behaviour named after the file,
+/// so `termite.rhai` becomes `behavior = "termite"`. Like
+/// `register_behavior`, has to happen before materials are loaded. Returns
+/// how many there were.
+pub fn register_script_behaviors<P: AsRef<Path>>(dir: P) -> Result<usize, MaterialError> {
+    let mut count = 0;
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e| e == "rhai") {
+            let Some(name) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            // Kept for reloading and errors, as well as by the registry
+            let behavior: &'static ScriptBehavior =
+                Box::leak(Box::new(ScriptBehavior::from_file(&path)?));
+            register_behavior(name, behavior)?;
+            SCRIPTS.lock().unwrap().push(behavior);
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// Reload every script from `register_script_behaviors` from its file. Ones
+/// that fail to load keep running as they were; their errors are returned.
+pub fn reload_scripts() -> Vec<MaterialError> {
+    SCRIPTS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter_map(|script| script.reload().err())
+        .collect()
+}
+
+/// The first error each script from `register_script_behaviors` has hit
+/// while running, since it was loaded, that hasn't been returned from here
+/// before.
+pub fn take_script_errors() -> Vec<MaterialError> {
+    SCRIPTS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter_map(|script| {
+            let mut error = script.error.lock().unwrap();
+            if error.reported {
+                return None;
+            }
+            error.reported = error.first.is_some();
+            drop(error);
+            script.error()
+        })
+        .collect()
+}
+
+impl ParticleBehavior for &'static ScriptBehavior {
+    fn update(&self, particle: &mut Particle, api: &mut WorldApi) {
+        (**self).update(particle, api)
+    }
+
+    fn premove(&self, particle: &mut Particle, dxdy: I8Vec2, api: &mut WorldApi) -> bool {
+        (**self).premove(particle, dxdy, api)
+    }
+
+    fn on_ignite(&self, particle: &mut Particle, api: &mut WorldApi) {
+        (**self).on_ignite(particle, api)
+    }
+
+    fn on_destroy(&self, particle: &Particle, api: &mut WorldApi) {
+        (**self).on_destroy(particle, api)
+    }
+}
+
+// ─── Api ─────────────────────────────────────────────────────────────────────
+
+// Every hook call gets a new number, so a handle kept from an earlier one
+// never matches the call that's running
+static NEXT_CALL: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    // The hook call running on this thread, and the api it was given
+    static CURRENT: Cell<Option<(u64, *mut WorldApi<'static>)>> = const { Cell::new(None) };
+}
+
+// Makes an api available to the script for as long as it's alive, and
+// takes it away again (even if the script panics) when it's dropped
+struct ApiCall {
+    id: u64,
+    previous: Option<(u64, *mut WorldApi<'static>)>,
+}
+
+impl ApiCall {
+    fn enter(api: &mut WorldApi) -> Self {
+        let id = NEXT_CALL.fetch_add(1, Ordering::Relaxed);
+        let api = (api as *mut WorldApi).cast::<WorldApi<'static>>();
+        let previous = CURRENT.replace(Some((id, api)));
+        ApiCall { id, previous }
+    }
+
+    fn handle(&self) -> ScriptApi {
+        ScriptApi { call: self.id }
+    }
+}
+
+impl Drop for ApiCall {
+    fn drop(&mut self) {
+        CURRENT.set(self.previous);
+    }
+}
+
+// What the script sees as `api`: just which call it belongs to. The api
+// itself never leaves `CURRENT`, so a handle the script hangs on to can't
+// reach it once that call is over.
+#[derive(Clone)]
+struct ScriptApi {
+    call: u64,
+}
+
+impl ScriptApi {
+    fn with_api<T>(
+        &self,
+        f: impl FnOnce(&mut WorldApi) -> Result<T, Box<EvalAltResult>>,
+    ) -> Result<T, Box<EvalAltResult>> {
+        match CURRENT.get() {
+            // SAFETY: CURRENT only holds the api while the ApiCall for it is
+            // alive, which is within ScriptBehavior::call, while it's
+            // mutably borrowed and used for nothing else. Nothing reached
+            // from here runs another hook, so this is the only reference.
+            Some((id, api)) if id == self.call => f(unsafe { &mut *api }),
+            _ => Err("api used outside the hook it was given to".into()),
+        }
+    }
+
+    fn neighbour(&mut self, dx: i64, dy: i64) -> Result<String, Box<EvalAltResult>> {
+        let dxdy = adjacent(dx, dy)?;
+        self.with_api(|api| Ok(api.neighbour(dxdy).particle_type.properties().label.clone()))
+    }
+
+    fn swap_with(&mut self, dx: i64, dy: i64) -> Result<(), Box<EvalAltResult>> {
+        let dxdy = adjacent(dx, dy)?;
+        self.with_api(|api| {
+            if api.neighbour(dxdy).particle_type == ParticleType::Border {
+                return Err("can't swap with the border".into());
+            }
+            api.swap_with(dxdy);
+            Ok(())
+        })
+    }
+
+    fn replace_with_new(
+        &mut self,
+        dx: i64,
+        dy: i64,
+        label: &str,
+    ) -> Result<(), Box<EvalAltResult>> {
+        let dxdy = adjacent(dx, dy)?;
+        let particle_type =
+            ParticleType::from_label(label).ok_or_else(|| format!("no material {}", label))?;
+        self.with_api(|api| {
+            api.replace_with_new(dxdy, particle_type);
+            Ok(())
+        })
+    }
+
+    fn random(&mut self) -> Result<f64, Box<EvalAltResult>> {
+        self.with_api(|api| Ok(api.random::<f64>()))
+    }
+
+    fn might_update(&mut self) -> Result<(), Box<EvalAltResult>> {
+        self.with_api(|api| {
+            api.might_update();
+            Ok(())
+        })
+    }
+}
+
+// The particle is never on the border, so everything next to it is in the
+// world
+fn adjacent(dx: i64, dy: i64) -> Result<(i16, i16), Box<EvalAltResult>> {
+    if dx.abs() > 1 || dy.abs() > 1 {
+        return Err(format!("({}, {}) isn't next to the particle", dx, dy).into());
+    }
+    Ok((dx as i16, dy as i16))
+}
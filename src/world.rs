@@ -1,36 +1,46 @@
 use super::*;
 use ::rand::{
-    distributions::uniform::SampleRange, prelude::Distribution, rngs::ThreadRng, seq::SliceRandom,
-    thread_rng, Rng,
+    distributions::uniform::SampleRange, prelude::Distribution, seq::SliceRandom, thread_rng, Rng,
+    SeedableRng,
 };
 use array2d::Array2D;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+mod explosion;
+mod history;
+mod replay;
+mod rigid_body;
+mod save;
+use history::EditHistory;
+pub use history::DEFAULT_HISTORY_BUDGET;
+pub use replay::{EditAction, Replay};
+use rigid_body::RigidBody;
+#[cfg(test)]
+use save::saved;
+pub use save::SaveError;
+
+/// The generator behind every random decision the simulation makes. ChaCha8
+/// gives the same stream for a given seed on every platform and rand version,
+/// unlike `StdRng`.
+pub type WorldRng = ChaCha8Rng;
 
 /* #region  */
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct ParticleSource {
     particle_type: ParticleType,
     replaces: bool,
 }
 
 impl ParticleSource {
-    fn draw(&self, x: usize, y: usize, painter: &Painter) {
-        let mut color: Color = self.particle_type.properties().base_color.into();
-        color.a = 0.5;
-        color.r -= 0.1;
-        color.g -= 0.1;
-        color.b -= 0.1;
-
-        painter.draw_source(
-            x,
-            y,
-            color,
-            self.replaces,
-            self.particle_type == ParticleType::Empty,
-        );
+    fn draw<R: Renderer>(&self, x: usize, y: usize, renderer: &R) {
+        renderer.draw_source(x, y, self.particle_type, self.replaces);
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum Direction {
     Up,
     Right,
@@ -49,24 +59,48 @@ impl Direction {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct Portal {
     partner_xy: Option<(usize, usize)>,
     // if you're standing where the portal is, which direction do you go to walk through it
     direction: Direction,
-    color: Color,
+    color: PColor,
 }
 
 impl Portal {
-    fn draw(&self, x: usize, y: usize, painter: &Painter) {
-        painter.draw_portal(x, y, self.direction, self.color);
+    fn draw<R: Renderer>(&self, x: usize, y: usize, renderer: &R) {
+        renderer.draw_portal(x, y, self.direction, self.color);
+    }
+}
+
+/// The strongest a fan can blow. A fan reaches FAN_REACH cells for every
+/// point of strength, and speeds things up to its strength in cells per tick.
+pub const MAX_FAN_STRENGTH: u8 = 10;
+const FAN_REACH: usize = 4;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Fan {
+    direction: Direction,
+    strength: u8,
+}
+
+impl Fan {
+    fn draw<R: Renderer>(&self, x: usize, y: usize, renderer: &R) {
+        renderer.draw_fan(x, y, self.direction, self.strength);
     }
 }
 
 /* #endregion */
 
 pub struct WorldApi<'a> {
-    world: &'a mut World,
+    // Shared, since chunks updated in parallel all use the same world. It's
+    // only made by update_chunk, whose callers make sure nothing else touches
+    // the particles within reach of the chunk while it's around, so that's
+    // all it changes.
+    world: &'a World,
+    // Kept apart from the world's own generator so that chunks updated in
+    // parallel can each have their own
+    rng: &'a mut WorldRng,
     xy: (usize, usize),
 }
 
@@ -75,7 +109,7 @@ impl<'a> WorldApi<'a> {
     where
         ::rand::distributions::Standard: Distribution<T>,
     {
-        self.world.rng.gen::<T>()
+        self.rng.gen::<T>()
     }
 
     pub fn random_range<T, R>(&mut self, slice: R) -> T
@@ -83,7 +117,7 @@ impl<'a> WorldApi<'a> {
         T: ::rand::distributions::uniform::SampleUniform,
         R: SampleRange<T>,
     {
-        self.world.rng.gen_range::<T, R>(slice)
+        self.rng.gen_range::<T, R>(slice)
     }
 
     pub fn neighbour<T>(&self, dxdy: T) -> &Particle
@@ -97,7 +131,10 @@ impl<'a> WorldApi<'a> {
     where
         (i16, i16): From<T>,
     {
-        self.world.relative_particle_mut(self.xy, dxdy.into())
+        let xy = self.world.relative_xy(self.xy, dxdy.into());
+        // SAFETY: see `world`. Borrowing self mutably keeps anything else
+        // from getting at the particle through this api meanwhile.
+        unsafe { self.world.get_particle_unchecked(xy) }
     }
 
     pub fn swap_with<T>(&mut self, dxdy: T)
@@ -105,8 +142,16 @@ impl<'a> WorldApi<'a> {
         (i16, i16): From<T>,
     {
         let other_xy = self.world.relative_xy(self.xy, dxdy.into());
-        self.world
-            .put_particle(self.xy, self.world.get_particle(other_xy).clone());
+        let other = *self.world.get_particle(other_xy);
+        let extras = *self.world.get_extras(self.xy);
+        let other_extras = *self.world.get_extras(other_xy);
+        self.put_particle(self.xy, other);
+        // Most particles have nothing in the side table, and there's no need
+        // to swap nothing for nothing
+        if extras != other_extras {
+            self.put_extras(self.xy, other_extras);
+            self.put_extras(other_xy, extras);
+        }
         self.xy = other_xy;
     }
 
@@ -115,11 +160,14 @@ impl<'a> WorldApi<'a> {
         (i16, i16): From<T>,
     {
         let xy = self.world.relative_xy(self.xy, dxdy.into());
-        self.world.add_new_particle(particle_type, xy, true);
+        if self.world.can_place(particle_type, xy, true) {
+            let new_particle = Particle::new(particle_type, self.rng);
+            self.put_particle(xy, new_particle);
+        }
     }
 
     pub fn new_particle(&mut self, particle_type: ParticleType) -> Particle {
-        Particle::new(particle_type, &mut self.world.rng)
+        Particle::new(particle_type, self.rng)
     }
 
     pub fn replace_with<T>(&mut self, dxdy: T, particle: Particle)
@@ -127,56 +175,298 @@ impl<'a> WorldApi<'a> {
         (i16, i16): From<T>,
     {
         let xy = self.world.relative_xy(self.xy, dxdy.into());
-        self.world.put_particle(xy, particle);
+        self.put_particle(xy, particle);
     }
 
     pub fn update_in_world(&mut self, particle: Particle) {
-        self.world.put_particle(self.xy, particle);
+        self.put_particle(self.xy, particle);
+    }
+
+    /// What's left of the fuel of the particle at dxdy.
+    pub fn fuel<T>(&self, dxdy: T) -> i16
+    where
+        (i16, i16): From<T>,
+    {
+        self.extras(dxdy).fuel
+    }
+
+    /// What's left of the durability of the particle at dxdy.
+    pub fn durability<T>(&self, dxdy: T) -> i16
+    where
+        (i16, i16): From<T>,
+    {
+        self.extras(dxdy).durability
+    }
+
+    /// Whether the particle at dxdy is a conductor carrying a spark.
+    pub fn is_sparked<T>(&self, dxdy: T) -> bool
+    where
+        (i16, i16): From<T>,
+    {
+        self.extras(dxdy).is_sparked()
+    }
+
+    pub(crate) fn extras<T>(&self, dxdy: T) -> ParticleExtras
+    where
+        (i16, i16): From<T>,
+    {
+        *self
+            .world
+            .get_extras(self.world.relative_xy(self.xy, dxdy.into()))
+    }
+
+    pub(crate) fn set_extras<T>(&mut self, dxdy: T, extras: ParticleExtras)
+    where
+        (i16, i16): From<T>,
+    {
+        let xy = self.world.relative_xy(self.xy, dxdy.into());
+        self.put_extras(xy, extras);
+    }
+
+    fn put_particle(&mut self, xy: (usize, usize), particle: Particle) {
+        // SAFETY: see `world`
+        unsafe { self.world.put_particle_unchecked(xy, particle) }
+    }
+
+    fn put_extras(&mut self, xy: (usize, usize), extras: ParticleExtras) {
+        // SAFETY: see `world`
+        unsafe { self.world.put_extras_unchecked(xy, extras) }
     }
 
     pub fn xy(&self) -> &(usize, usize) {
         &self.xy
     }
 
+    /// Set off an explosion here once every particle has updated.
+    pub fn explode(&mut self, explosion: Explosion) {
+        let (chunk_xy, _) = self.world.global_xy_to_chunk_xy(self.xy);
+        // SAFETY: see `world`. The chunk xy is in is within reach.
+        unsafe { self.world.chunk_grid[chunk_xy].explosions_unchecked() }
+            .push((self.xy, explosion));
+    }
+
+    /// How hard the wind is blowing, and which way (see `World::set_wind`).
+    pub fn wind(&self) -> i8 {
+        self.world.wind
+    }
+
     pub fn might_update(&mut self) {
         let (chunk_xy, local_xy) = self.world.global_xy_to_chunk_xy(self.xy);
         self.world.wake_chunk_from_local(chunk_xy, local_xy);
     }
 }
 
-#[derive(Clone)]
+// How far around a particle that changes the area woken up for the next frame
+// reaches
+const WAKE_MARGIN: usize = 2;
+
+/// The part of a chunk that needs updating (and redrawing), in the chunk's
+/// local coordinates. Both corners are inclusive.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct DirtyRect {
+    min: (usize, usize),
+    max: (usize, usize),
+}
+
+impl DirtyRect {
+    fn full(chunk_size: usize) -> Self {
+        Self {
+            min: (0, 0),
+            max: (chunk_size - 1, chunk_size - 1),
+        }
+    }
+
+    fn contains(&self, xy: (usize, usize)) -> bool {
+        (self.min.0..=self.max.0).contains(&xy.0) && (self.min.1..=self.max.1).contains(&xy.1)
+    }
+
+    fn union(self, other: Option<DirtyRect>) -> DirtyRect {
+        match other {
+            Some(other) => DirtyRect {
+                min: (self.min.0.min(other.min.0), self.min.1.min(other.min.1)),
+                max: (self.max.0.max(other.max.0), self.max.1.max(other.max.1)),
+            },
+            None => self,
+        }
+    }
+}
+
+// A DirtyRect that can be grown from several threads at once, since particles
+// near the edge of a chunk being updated on one thread can wake a chunk that's
+// next to one being updated on another. Empty while min > max.
+struct AtomicDirtyRect {
+    min_x: AtomicUsize,
+    min_y: AtomicUsize,
+    max_x: AtomicUsize,
+    max_y: AtomicUsize,
+}
+
+impl AtomicDirtyRect {
+    fn new(rect: Option<DirtyRect>) -> Self {
+        let atomic = Self {
+            min_x: AtomicUsize::new(usize::MAX),
+            min_y: AtomicUsize::new(usize::MAX),
+            max_x: AtomicUsize::new(0),
+            max_y: AtomicUsize::new(0),
+        };
+        if let Some(rect) = rect {
+            atomic.grow(rect);
+        }
+        atomic
+    }
+
+    fn grow(&self, rect: DirtyRect) {
+        // Almost everything woken is already inside the rect, so only pay for
+        // an atomic update when it isn't
+        if rect.min.0 < self.min_x.load(Ordering::Relaxed) {
+            self.min_x.fetch_min(rect.min.0, Ordering::Relaxed);
+        }
+        if rect.min.1 < self.min_y.load(Ordering::Relaxed) {
+            self.min_y.fetch_min(rect.min.1, Ordering::Relaxed);
+        }
+        if rect.max.0 > self.max_x.load(Ordering::Relaxed) {
+            self.max_x.fetch_max(rect.max.0, Ordering::Relaxed);
+        }
+        if rect.max.1 > self.max_y.load(Ordering::Relaxed) {
+            self.max_y.fetch_max(rect.max.1, Ordering::Relaxed);
+        }
+    }
+
+    fn get(&self) -> Option<DirtyRect> {
+        let rect = DirtyRect {
+            min: (
+                self.min_x.load(Ordering::Relaxed),
+                self.min_y.load(Ordering::Relaxed),
+            ),
+            max: (
+                self.max_x.load(Ordering::Relaxed),
+                self.max_y.load(Ordering::Relaxed),
+            ),
+        };
+        (rect.min.0 <= rect.max.0 && rect.min.1 <= rect.max.1).then_some(rect)
+    }
+
+    fn take(&mut self) -> Option<DirtyRect> {
+        let rect = self.get();
+        *self = Self::new(None);
+        rect
+    }
+}
+
+// The particles, their extras and explosions are what particles updating
+// change, so they're in UnsafeCells for threads updating different chunks to
+// share the world. Through a shared reference they're only changed by the
+// `_unchecked` methods, whose callers have to make sure nothing else is using
+// the chunk.
 struct WorldChunk {
-    particle_grid: Array2D<Particle>,
-    update_this_frame: bool,
-    update_next_frame: bool,
+    particle_grid: UnsafeCell<Array2D<Particle>>,
+    // The side table of fuel, durability and charge, in the same layout
+    extras_grid: UnsafeCell<Array2D<ParticleExtras>>,
+    // Only particles in here get updated this frame
+    dirty_this_frame: Option<DirtyRect>,
+    dirty_next_frame: AtomicDirtyRect,
+    // Set off by particles in this chunk this frame, waiting to go off
+    explosions: UnsafeCell<Vec<((usize, usize), Explosion)>>,
+}
+
+// SAFETY: see above. Everything else is either atomic or only changed through
+// a mutable reference.
+unsafe impl Sync for WorldChunk {}
+
+impl Clone for WorldChunk {
+    fn clone(&self) -> Self {
+        Self {
+            particle_grid: UnsafeCell::new(self.particles().clone()),
+            extras_grid: UnsafeCell::new(self.extras().clone()),
+            dirty_this_frame: self.dirty_this_frame,
+            dirty_next_frame: AtomicDirtyRect::new(self.dirty_next_frame.get()),
+            // SAFETY: nothing changes a chunk while it's being cloned
+            explosions: UnsafeCell::new(unsafe { &*self.explosions.get() }.clone()),
+        }
+    }
 }
 
 impl WorldChunk {
-    fn new(chunk_size: usize, rng: &mut ThreadRng) -> Self {
-        let particle_grid = Array2D::filled_with(
-            Particle::new(ParticleType::Empty, rng),
+    fn new(chunk_size: usize, rng: &mut WorldRng) -> Self {
+        let mut empty = Particle::new(ParticleType::Empty, rng);
+        empty.take_fresh();
+        let particle_grid = Array2D::filled_with(empty, chunk_size, chunk_size);
+        let extras_grid = Array2D::filled_with(
+            ParticleExtras::new(ParticleType::Empty),
             chunk_size,
             chunk_size,
         );
 
         Self {
-            particle_grid,
-            update_this_frame: true,
-            update_next_frame: true,
+            particle_grid: UnsafeCell::new(particle_grid),
+            extras_grid: UnsafeCell::new(extras_grid),
+            dirty_this_frame: Some(DirtyRect::full(chunk_size)),
+            dirty_next_frame: AtomicDirtyRect::new(Some(DirtyRect::full(chunk_size))),
+            explosions: UnsafeCell::new(vec![]),
         }
     }
 
-    fn shift_update_flag(&mut self) {
-        self.update_this_frame = self.update_next_frame;
-        self.update_next_frame = false;
+    fn particles(&self) -> &Array2D<Particle> {
+        // SAFETY: only the _unchecked methods change the particles without a
+        // mutable reference, and their callers make sure nothing else is
+        // looking at them
+        unsafe { &*self.particle_grid.get() }
     }
 
-    fn refresh_all_particles(&mut self) {
-        // row_len() and column_len() seem wrong to me? Double check if ever
-        // switching to non-square chunks
-        for x in 0..self.particle_grid.column_len() {
-            for y in 0..self.particle_grid.row_len() {
-                self.particle_grid[(x, y)].refresh();
+    fn particles_mut(&mut self) -> &mut Array2D<Particle> {
+        self.particle_grid.get_mut()
+    }
+
+    /// # Safety
+    /// Nothing else can be using this chunk's particles until the reference
+    /// is dropped, on this thread or any other.
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn particles_unchecked(&self) -> &mut Array2D<Particle> {
+        &mut *self.particle_grid.get()
+    }
+
+    fn extras(&self) -> &Array2D<ParticleExtras> {
+        // SAFETY: as for particles
+        unsafe { &*self.extras_grid.get() }
+    }
+
+    /// # Safety
+    /// Nothing else can be using this chunk's extras until the reference is
+    /// dropped, on this thread or any other.
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn extras_unchecked(&self) -> &mut Array2D<ParticleExtras> {
+        &mut *self.extras_grid.get()
+    }
+
+    /// # Safety
+    /// Nothing else can be using this chunk's explosions until the reference
+    /// is dropped, on this thread or any other.
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn explosions_unchecked(&self) -> &mut Vec<((usize, usize), Explosion)> {
+        &mut *self.explosions.get()
+    }
+
+    fn take_explosions(&mut self) -> Vec<((usize, usize), Explosion)> {
+        std::mem::take(self.explosions.get_mut())
+    }
+
+    fn shift_dirty_rect(&mut self) {
+        self.dirty_this_frame = self.dirty_next_frame.take();
+    }
+
+    // Everything that was updated this frame or has changed since, which is
+    // everything that might need refreshing or redrawing
+    fn touched_rect(&self) -> Option<DirtyRect> {
+        match self.dirty_this_frame {
+            Some(rect) => Some(rect.union(self.dirty_next_frame.get())),
+            None => self.dirty_next_frame.get(),
+        }
+    }
+
+    fn refresh_particles(&mut self, rect: DirtyRect) {
+        for x in rect.min.0..=rect.max.0 {
+            for y in rect.min.1..=rect.max.1 {
+                self.particles_mut()[(x, y)].refresh();
             }
         }
     }
@@ -188,18 +478,40 @@ pub struct World {
     chunk_grid: Array2D<WorldChunk>,
     source_grid: Array2D<Option<ParticleSource>>,
     portal_grid: Array2D<Option<Portal>>,
+    fan_grid: Array2D<Option<Fan>>,
     chunk_size: usize,
+    // log2 of the chunk size, when it's a power of two, so that finding a
+    // particle's chunk can shift instead of divide
+    chunk_shift: Option<u32>,
     width: usize,
     height: usize,
-    rng: ThreadRng,
+    seed: u64,
+    rng: WorldRng,
+    parallel: bool,
+    has_portals: bool,
+    // Cells per tick, blowing everything that moves sideways; negative is to
+    // the left
+    wind: i8,
+    rigid_bodies: Vec<RigidBody>,
+    // The order particles within a chunk get updated in, reshuffled each frame
+    idx_range: Vec<usize>,
+    history: EditHistory,
+    // Edits made since recording started, if it has
+    replay: Option<Replay>,
 }
 
 impl World {
     pub fn new(width: usize, height: usize, chunk_size: usize) -> Self {
+        Self::with_seed(width, height, chunk_size, thread_rng().gen())
+    }
+
+    /// Create a world whose simulation is fully determined by `seed`: the
+    /// same seed and the same edits always produce the same world.
+    pub fn with_seed(width: usize, height: usize, chunk_size: usize, seed: u64) -> Self {
         assert_eq!(width % chunk_size, 0);
         assert_eq!(height % chunk_size, 0);
 
-        let mut rng = thread_rng();
+        let mut rng = WorldRng::seed_from_u64(seed);
 
         let chunk_grid = Array2D::filled_with(
             WorldChunk::new(chunk_size, &mut rng),
@@ -211,16 +523,29 @@ impl World {
         //     Array2D::filled_with(Particle::new(ParticleType::Empty, &mut rng), width, height);
         let source_grid = Array2D::filled_with(None, width, height);
         let portal_grid = Array2D::filled_with(None, width, height);
+        let fan_grid = Array2D::filled_with(None, width, height);
 
         let mut new_world = Self {
             // particle_grid,
             chunk_grid,
             source_grid,
             portal_grid,
+            fan_grid,
             chunk_size,
+            chunk_shift: chunk_size
+                .is_power_of_two()
+                .then(|| chunk_size.trailing_zeros()),
             width,
             height,
+            seed,
             rng,
+            parallel: false,
+            has_portals: false,
+            wind: 0,
+            rigid_bodies: vec![],
+            idx_range: Vec::new(),
+            history: EditHistory::new(),
+            replay: None,
         };
 
         for y in 0..height {
@@ -243,11 +568,54 @@ impl World {
         self.height
     }
 
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn parallel(&self) -> bool {
+        self.parallel
+    }
+
+    /// Update chunks on several threads at once. Worlds with portals, or
+    /// with chunks too small to keep particles in neighbouring chunks from
+    /// running into each other, still update on one thread, as does
+    /// everything once a material uses a behaviour from `register_behavior`.
+    pub fn set_parallel(&mut self, parallel: bool) {
+        if parallel != self.parallel {
+            self.record_action(EditAction::SetParallel(parallel));
+        }
+        self.parallel = parallel;
+    }
+
+    pub fn wind(&self) -> i8 {
+        self.wind
+    }
+
+    /// Blow everything that moves sideways, at up to `wind` cells per tick
+    /// (to the left if it's negative). Light things catch the wind more
+    /// easily than heavy ones.
+    pub fn set_wind(&mut self, wind: i8) {
+        if wind != self.wind {
+            self.record_action(EditAction::SetWind(wind));
+            // Things already settled need to feel it too
+            self.wake_everything();
+        }
+        self.wind = wind;
+    }
+
     // ─── Update Methods ──────────────────────────────────────────────────────────────────
     pub fn update_all(&mut self) {
         self.update_all_sources();
-        self.shift_chunks_update_flag();
+        self.update_all_fans();
+        self.shift_chunks_dirty_rect();
         self.update_all_particles();
+        self.detonate_explosions();
+        self.update_rigid_bodies();
+        self.record_tick();
     }
 
     fn update_all_sources(&mut self) {
@@ -256,93 +624,272 @@ impl World {
                 let xy = (x, y);
                 if let Some(source) = self.source_grid[xy].clone() {
                     if self.rng.gen() {
-                        self.add_new_particle(source.particle_type, xy, source.replaces);
+                        self.place_new_particle(source.particle_type, xy, source.replaces);
+                    }
+                }
+            }
+        }
+    }
+
+    // Fans push whatever can move in the column of cells in front of them,
+    // more slowly the further away it is. Anything that can't move blocks the
+    // air.
+    fn update_all_fans(&mut self) {
+        for x in 1..self.width {
+            for y in 1..self.height {
+                let Some(fan) = self.fan_grid[(x, y)].clone() else {
+                    continue;
+                };
+                let (dx, dy) = fan.direction.dxdy();
+                let reach = fan.strength as usize * FAN_REACH;
+                for distance in 1..=reach {
+                    let fx = x as isize + dx as isize * distance as isize;
+                    let fy = y as isize + dy as isize * distance as isize;
+                    if fx < 0 || fy < 0 || fx >= self.width as isize || fy >= self.height as isize {
+                        break;
                     }
+                    let xy = (fx as usize, fy as usize);
+                    let particle_type = self.get_particle(xy).particle_type;
+                    if particle_type == ParticleType::Empty {
+                        continue;
+                    }
+                    if !particle_type.properties().moves {
+                        break;
+                    }
+                    let falloff = 1.0 - (distance - 1) as f32 / reach as f32;
+                    let speed = (fan.strength as f32 * falloff).ceil() as i8;
+                    let roll = self.rng.gen::<f32>();
+                    self.get_particle_mut(xy)
+                        .blow(i8vec2(dx as i8, dy as i8), speed, roll);
                 }
             }
         }
     }
 
-    fn shift_chunks_update_flag(&mut self) {
+    fn shift_chunks_dirty_rect(&mut self) {
         let num_chunks_x = self.chunk_grid.column_len();
         let num_chunks_y = self.chunk_grid.row_len();
 
         for chunk_x in 0..num_chunks_x {
             for chunk_y in 0..num_chunks_y {
-                self.chunk_grid[(chunk_x, chunk_y)].shift_update_flag();
+                self.chunk_grid[(chunk_x, chunk_y)].shift_dirty_rect();
             }
         }
     }
 
     fn update_all_particles(&mut self) {
+        // Reuse the same buffer every frame rather than allocating a new one
+        let mut idx_range = std::mem::take(&mut self.idx_range);
+        idx_range.clear();
+        idx_range.extend(0..(self.chunk_size * self.chunk_size));
+        idx_range.shuffle(&mut self.rng);
+
+        if self.can_update_in_parallel() {
+            self.update_chunks_parallel(&idx_range);
+        } else {
+            self.update_chunks_serial(&idx_range);
+        }
+        self.idx_range = idx_range;
+    }
+
+    fn update_chunks_serial(&mut self, idx_range: &[usize]) {
         let num_chunks_x = self.width / self.chunk_size;
         let num_chunks_y = self.height / self.chunk_size;
 
-        let mut idx_range: Vec<usize> = (0..(self.chunk_size * self.chunk_size)).collect();
         let mut chunk_x_range: Vec<usize> = (0..num_chunks_x).collect();
         let mut chunk_y_range: Vec<usize> = (0..num_chunks_y).collect();
 
-        idx_range.shuffle(&mut self.rng);
         chunk_x_range.shuffle(&mut self.rng);
         chunk_y_range.shuffle(&mut self.rng);
 
+        // Borrow the generator out of the world for the WorldApi to use
+        let mut rng = std::mem::replace(&mut self.rng, WorldRng::seed_from_u64(0));
         for chunk_x in chunk_x_range.iter() {
             for chunk_y in chunk_y_range.iter() {
-                if self.chunk_grid[(*chunk_x, *chunk_y)].update_this_frame {
-                    for idx in idx_range.iter() {
-                        let local_xy = self.local_index_to_xy(*idx);
+                // SAFETY: there's only this one thread
+                unsafe { self.update_chunk((*chunk_x, *chunk_y), idx_range, &mut rng) };
+            }
+        }
+        self.rng = rng;
+    }
 
-                        // Clone the particle and make sure it hasn't been updated
+    // How far from itself a particle can reach in one update, by moving or by
+    // looking at or changing its neighbours, or None if there's no telling.
+    // Explosions, fans and rigid bodies reach further, but they're dealt with
+    // on one thread, before or after the particles update.
+    fn max_particle_reach() -> Option<usize> {
+        // Behaviours from outside the crate could do anything
+        if uses_registered_behaviors() {
+            return None;
+        }
+        // Moving with its velocity (which never gets past its terminal
+        // velocity) or flowing sideways, but not both in one tick
+        let max_move = ParticleType::all()
+            .map(|t| {
+                let properties = t.properties();
+                let dispersion = properties.dispersion_rate.unwrap_or(1) as usize;
+                let fall = properties
+                    .terminal_velocity_sq
+                    .map_or(1, |v| (v as f32).sqrt().ceil() as usize);
+                dispersion.max(fall)
+            })
+            .max()
+            .unwrap_or(1);
+        // Then it looks around where it ended up. Reactions, heat and sparks
+        // only go as far as the next cell; fungus and plants look two cells
+        // away, and seeds put a root in the cell below.
+        Some(max_move + 2)
+    }
+
+    fn can_update_in_parallel(&self) -> bool {
+        self.parallel
+            && !self.has_portals
+            && World::max_particle_reach().is_some_and(|reach| self.chunk_size >= reach)
+    }
 
-                        let particle =
-                            &self.chunk_grid[(*chunk_x, *chunk_y)].particle_grid[(local_xy)];
+    /// Chunks are updated in nine phases, one for each position in a 3x3
+    /// block of chunks. Within a phase every chunk being updated is at least
+    /// two whole chunks away from any other, and nothing reaches further than
+    /// a chunk in one update, so no two threads ever touch the same particle.
+    /// Each chunk gets its own generator, seeded from the world's in a fixed
+    /// order, so the result doesn't depend on how many threads there are.
+    fn update_chunks_parallel(&mut self, idx_range: &[usize]) {
+        let num_chunks_x = self.width / self.chunk_size;
+        let num_chunks_y = self.height / self.chunk_size;
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+
+        let mut phases: Vec<(usize, usize)> = (0..9).map(|i| (i % 3, i / 3)).collect();
+        phases.shuffle(&mut self.rng);
+
+        for (phase_x, phase_y) in phases {
+            let chunks: Vec<((usize, usize), u64)> = (phase_y..num_chunks_y)
+                .step_by(3)
+                .flat_map(|chunk_y| {
+                    (phase_x..num_chunks_x)
+                        .step_by(3)
+                        .map(move |chunk_x| (chunk_x, chunk_y))
+                })
+                .filter(|chunk_xy| self.chunk_grid[*chunk_xy].dirty_this_frame.is_some())
+                .collect::<Vec<_>>()
+                .into_iter()
+                .map(|chunk_xy| (chunk_xy, self.rng.gen()))
+                .collect();
+
+            if chunks.len() <= 1 || threads <= 1 {
+                for (chunk_xy, seed) in chunks {
+                    let mut rng = WorldRng::seed_from_u64(seed);
+                    // SAFETY: there's only this one thread
+                    unsafe { self.update_chunk(chunk_xy, idx_range, &mut rng) };
+                }
+                continue;
+            }
 
-                        if particle.particle_type == ParticleType::Empty
-                            || particle.particle_type == ParticleType::Border
-                            || particle.updated
-                        {
-                            continue;
+            let world: &World = self;
+            std::thread::scope(|scope| {
+                for group in chunks.chunks(chunks.len().div_ceil(threads)) {
+                    scope.spawn(move || {
+                        for (chunk_xy, seed) in group {
+                            let mut rng = WorldRng::seed_from_u64(*seed);
+                            // SAFETY: every thread only touches particles
+                            // within a chunk of the ones in its group (see
+                            // above), and those areas don't overlap between
+                            // groups
+                            unsafe { world.update_chunk(*chunk_xy, idx_range, &mut rng) };
                         }
+                    });
+                }
+            });
+        }
+    }
+
+    /// # Safety
+    /// Nothing else can touch the particles within a chunk (or
+    /// max_particle_reach, if that's less) of this one while it updates, on
+    /// any thread.
+    unsafe fn update_chunk(
+        &self,
+        chunk_xy: (usize, usize),
+        idx_range: &[usize],
+        rng: &mut WorldRng,
+    ) {
+        let Some(dirty) = self.chunk_grid[chunk_xy].dirty_this_frame else {
+            return;
+        };
 
-                        self.chunk_grid[(*chunk_x, *chunk_y)].particle_grid[(local_xy)].updated =
-                            true;
+        for idx in idx_range.iter() {
+            let local_xy = self.local_index_to_xy(*idx);
+            if !dirty.contains(local_xy) {
+                continue;
+            }
 
-                        let mut particle_clone =
-                            self.chunk_grid[(*chunk_x, *chunk_y)].particle_grid[(local_xy)].clone();
+            // Copy the particle and make sure it hasn't been updated
 
-                        let global_xy = self.chunk_xy_to_global_xy((*chunk_x, *chunk_y), local_xy);
+            let particle = &mut self.chunk_grid[chunk_xy].particles_unchecked()[local_xy];
 
-                        particle_clone.update(WorldApi {
-                            world: self,
-                            xy: global_xy,
-                        });
-                    }
-                }
+            if particle.particle_type == ParticleType::Empty
+                || particle.particle_type == ParticleType::Border
+                || particle.updated()
+            {
+                continue;
             }
+
+            particle.set_updated();
+            let mut particle_copy = *particle;
+
+            let global_xy = self.chunk_xy_to_global_xy(chunk_xy, local_xy);
+
+            particle_copy.update(WorldApi {
+                world: self,
+                rng,
+                xy: global_xy,
+            });
         }
     }
 
     // ─── Creation Methods ────────────────────────────────────────────────────────────────
+    /// Returns whether there was room for the particle.
     pub fn add_new_particle(
         &mut self,
         new_particle_type: ParticleType,
         xy: (usize, usize),
         replace: bool,
-    ) {
+    ) -> bool {
+        self.record_action(EditAction::AddParticle {
+            particle_type: new_particle_type,
+            xy,
+            replace,
+        });
+        self.record_edit(xy);
+        self.place_new_particle(new_particle_type, xy, replace)
+    }
+
+    // add_new_particle without recording it as part of an edit, for sources
+    fn place_new_particle(
+        &mut self,
+        new_particle_type: ParticleType,
+        xy: (usize, usize),
+        replace: bool,
+    ) -> bool {
+        let can_place = self.can_place(new_particle_type, xy, replace);
+        if can_place {
+            let new_particle = Particle::new(new_particle_type, &mut self.rng);
+            self.put_particle(xy, new_particle);
+        }
+        can_place
+    }
+
+    fn can_place(
+        &self,
+        new_particle_type: ParticleType,
+        xy: (usize, usize),
+        replace: bool,
+    ) -> bool {
         let old_particle_type = self.get_particle(xy).particle_type;
 
         match (new_particle_type, old_particle_type) {
-            (_, ParticleType::Border) => {}
-            (ParticleType::Empty, _) | (_, ParticleType::Empty) => {
-                let new_particle = Particle::new(new_particle_type, &mut self.rng);
-                self.put_particle(xy, new_particle);
-            }
-            _ => {
-                if replace {
-                    let new_particle = Particle::new(new_particle_type, &mut self.rng);
-                    self.put_particle(xy, new_particle);
-                }
-            }
+            (_, ParticleType::Border) => false,
+            (ParticleType::Empty, _) | (_, ParticleType::Empty) => true,
+            _ => replace,
         }
     }
 
@@ -353,10 +900,17 @@ impl World {
         source_replaces: bool,
         replace: bool,
     ) {
+        self.record_action(EditAction::AddSource {
+            particle_type: source_type,
+            xy,
+            source_replaces,
+            replace,
+        });
         if self.source_grid[xy].is_some() && !replace {
             return;
         };
 
+        self.record_edit(xy);
         self.source_grid[xy] = Some(ParticleSource {
             particle_type: source_type,
             replaces: source_replaces,
@@ -368,13 +922,21 @@ impl World {
         xy: (usize, usize),
         partner_xy: Option<(usize, usize)>,
         direction: Direction,
-        color: Color,
+        color: PColor,
     ) -> bool {
+        self.record_action(EditAction::AddPortal {
+            xy,
+            partner_xy,
+            direction,
+            color,
+        });
         if self.portal_exists_at(xy) {
             return false;
         }
 
+        self.record_edit(xy);
         if let Some(partner_xy) = partner_xy {
+            self.record_edit(partner_xy);
             if let Some(ref mut partner) = self.portal_grid[partner_xy] {
                 partner.partner_xy = Some(xy);
             } else {
@@ -387,9 +949,36 @@ impl World {
             direction,
             color,
         });
+        self.has_portals = true;
         true
     }
 
+    /// Put a fan at `xy`, blowing towards `direction`. Strength is capped at
+    /// MAX_FAN_STRENGTH.
+    pub fn add_new_fan(
+        &mut self,
+        xy: (usize, usize),
+        direction: Direction,
+        strength: u8,
+        replace: bool,
+    ) {
+        self.record_action(EditAction::AddFan {
+            xy,
+            direction,
+            strength,
+            replace,
+        });
+        if self.fan_grid[xy].is_some() && !replace {
+            return;
+        }
+
+        self.record_edit(xy);
+        self.fan_grid[xy] = Some(Fan {
+            direction,
+            strength: strength.clamp(1, MAX_FAN_STRENGTH),
+        });
+    }
+
     pub fn portal_exists_at(&self, xy: (usize, usize)) -> bool {
         if self.portal_grid[xy].is_some() {
             return true;
@@ -399,53 +988,59 @@ impl World {
 
     // ─── Deletion Methods ────────────────────────────────────────────────────────────────
     pub fn delete_source(&mut self, xy: (usize, usize)) {
+        self.record_action(EditAction::DeleteSource { xy });
+        self.record_edit(xy);
         self.source_grid[xy] = None;
     }
 
+    pub fn delete_fan(&mut self, xy: (usize, usize)) {
+        self.record_action(EditAction::DeleteFan { xy });
+        self.record_edit(xy);
+        self.fan_grid[xy] = None;
+    }
+
     // ─── Other ───────────────────────────────────────────────────────────────────────────
-    pub fn draw_and_refresh(&mut self, painter: &mut Painter, debug_chunks: bool) {
+    pub fn draw_and_refresh<R: Renderer>(&mut self, renderer: &mut R, debug_chunks: bool) {
         let num_chunks_x = self.width / self.chunk_size;
         let num_chunks_y = self.height / self.chunk_size;
 
         for chunk_x in 0..num_chunks_x {
             for chunk_y in 0..num_chunks_y {
-                self.chunk_grid[(chunk_x, chunk_y)].refresh_all_particles();
-                if self.chunk_grid[(chunk_x, chunk_y)].update_this_frame
-                    || self.chunk_grid[(chunk_x, chunk_y)].update_next_frame
-                {
-                    for local_y in 0..self.chunk_size {
-                        for local_x in 0..self.chunk_size {
-                            let (global_x, global_y) =
-                                self.chunk_xy_to_global_xy((chunk_x, chunk_y), (local_x, local_y));
-                            // self.chunk_grid[(chunk_x, chunk_y)].particle_grid[(local_x, local_y)]
-                            //     .refresh();
-                            painter.update_image_with_particle(
-                                global_x,
-                                global_y,
-                                self.width,
-                                self.chunk_grid[(chunk_x, chunk_y)].particle_grid
-                                    [(local_x, local_y)]
-                                    .color,
-                            );
-                        }
+                let chunk = &mut self.chunk_grid[(chunk_x, chunk_y)];
+                let Some(rect) = chunk.touched_rect() else {
+                    continue;
+                };
+                chunk.refresh_particles(rect);
+
+                for local_y in rect.min.1..=rect.max.1 {
+                    for local_x in rect.min.0..=rect.max.0 {
+                        let (global_x, global_y) =
+                            self.chunk_xy_to_global_xy((chunk_x, chunk_y), (local_x, local_y));
+                        renderer.update_image_with_particle(
+                            global_x,
+                            global_y,
+                            self.width,
+                            self.chunk_grid[(chunk_x, chunk_y)].particles()[(local_x, local_y)]
+                                .color,
+                        );
                     }
                 }
             }
         }
 
-        painter.draw_screen(self.width as u16, self.height as u16);
+        renderer.draw_screen(self.width as u16, self.height as u16);
 
         if debug_chunks {
             for chunk_x in 0..num_chunks_x {
                 for chunk_y in 0..num_chunks_y {
-                    if self.chunk_grid[(chunk_x, chunk_y)].update_next_frame {
+                    if let Some(rect) = self.chunk_grid[(chunk_x, chunk_y)].dirty_next_frame.get() {
                         let (global_x, global_y) =
-                            self.chunk_xy_to_global_xy((chunk_x, chunk_y), (0, 0));
-                        painter.debug_chunk(
+                            self.chunk_xy_to_global_xy((chunk_x, chunk_y), rect.min);
+                        renderer.debug_chunk(
                             global_x,
                             global_y,
-                            self.chunk_size,
-                            self.chunk_size,
+                            rect.max.0 - rect.min.0 + 1,
+                            rect.max.1 - rect.min.1 + 1,
                             format!("({},{})", chunk_x, chunk_y).as_str(),
                         );
                     }
@@ -456,10 +1051,13 @@ impl World {
         for y in 0..self.height {
             for x in 0..self.width {
                 if let Some(portal) = &self.portal_grid[(x, y)] {
-                    portal.draw(x, y, painter);
+                    portal.draw(x, y, renderer);
                 }
                 if let Some(source) = &self.source_grid[(x, y)] {
-                    source.draw(x, y, painter);
+                    source.draw(x, y, renderer);
+                }
+                if let Some(fan) = &self.fan_grid[(x, y)] {
+                    fan.draw(x, y, renderer);
                 }
             }
         }
@@ -471,6 +1069,13 @@ impl World {
 
     fn global_xy_to_chunk_xy(&self, xy: (usize, usize)) -> ((usize, usize), (usize, usize)) {
         let (global_x, global_y) = xy;
+        if let Some(shift) = self.chunk_shift {
+            let mask = self.chunk_size - 1;
+            return (
+                (global_x >> shift, global_y >> shift),
+                (global_x & mask, global_y & mask),
+            );
+        }
         let chunk_x = global_x / self.chunk_size;
         let local_x = global_x % self.chunk_size;
         let chunk_y = global_y / self.chunk_size;
@@ -491,47 +1096,110 @@ impl World {
 
     pub fn get_particle(&self, xy: (usize, usize)) -> &Particle {
         let (chunk_xy, local_xy) = self.global_xy_to_chunk_xy(xy);
-        &self.chunk_grid[chunk_xy].particle_grid[local_xy]
+        &self.chunk_grid[chunk_xy].particles()[local_xy]
     }
 
     fn get_particle_mut(&mut self, xy: (usize, usize)) -> &mut Particle {
+        // SAFETY: borrowing the world mutably means nothing else is using it
+        unsafe { self.get_particle_unchecked(xy) }
+    }
+
+    /// Put `particle` at xy. If it's a new one, the fuel, durability and
+    /// charge there start over too; otherwise they're left for whoever's
+    /// moving it to bring along with put_extras.
+    fn put_particle(&mut self, xy: (usize, usize), particle: Particle) {
+        // SAFETY: as above
+        unsafe { self.put_particle_unchecked(xy, particle) }
+    }
+
+    fn get_extras(&self, xy: (usize, usize)) -> &ParticleExtras {
+        let (chunk_xy, local_xy) = self.global_xy_to_chunk_xy(xy);
+        &self.chunk_grid[chunk_xy].extras()[local_xy]
+    }
+
+    fn put_extras(&mut self, xy: (usize, usize), extras: ParticleExtras) {
+        // SAFETY: as above
+        unsafe { self.put_extras_unchecked(xy, extras) }
+    }
+
+    /// # Safety
+    /// Nothing else can be using the chunk xy is in until the reference is
+    /// dropped, on this thread or any other.
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn get_particle_unchecked(&self, xy: (usize, usize)) -> &mut Particle {
         let (chunk_xy, local_xy) = self.global_xy_to_chunk_xy(xy);
         self.wake_chunk_from_local(chunk_xy, local_xy);
-        &mut self.chunk_grid[chunk_xy].particle_grid[local_xy]
+        &mut self.chunk_grid[chunk_xy].particles_unchecked()[local_xy]
     }
 
-    fn put_particle(&mut self, xy: (usize, usize), particle: Particle) {
+    /// # Safety
+    /// Nothing else can be using the chunk xy is in meanwhile, on this thread
+    /// or any other.
+    unsafe fn put_particle_unchecked(&self, xy: (usize, usize), mut particle: Particle) {
+        if particle.take_fresh() {
+            self.put_extras_unchecked(xy, ParticleExtras::new(particle.particle_type));
+        }
         let (chunk_xy, local_xy) = self.global_xy_to_chunk_xy(xy);
-        if self.chunk_grid[chunk_xy].particle_grid[local_xy] != particle {
+        let particles = self.chunk_grid[chunk_xy].particles_unchecked();
+        if particles[local_xy] != particle {
             self.wake_chunk_from_local(chunk_xy, local_xy);
-            self.chunk_grid[chunk_xy].particle_grid[local_xy] = particle;
+            particles[local_xy] = particle;
         }
     }
 
-    fn wake_chunk_from_local(&mut self, chunk_xy: (usize, usize), local_xy: (usize, usize)) {
-        // Wake up this chunk.
-        self.chunk_grid[chunk_xy].update_next_frame = true;
+    /// # Safety
+    /// As for put_particle_unchecked.
+    unsafe fn put_extras_unchecked(&self, xy: (usize, usize), extras: ParticleExtras) {
+        let (chunk_xy, local_xy) = self.global_xy_to_chunk_xy(xy);
+        let extras_grid = self.chunk_grid[chunk_xy].extras_unchecked();
+        if extras_grid[local_xy] != extras {
+            self.wake_chunk_from_local(chunk_xy, local_xy);
+            extras_grid[local_xy] = extras;
+        }
+    }
 
-        let (chunk_x, chunk_y) = chunk_xy;
-        let (local_x, local_y) = local_xy;
+    fn wake_everything(&self) {
+        let chunk_size = self.chunk_size;
+        for chunk in self.chunk_grid.elements_row_major_iter() {
+            chunk.dirty_next_frame.grow(DirtyRect::full(chunk_size));
+        }
+    }
 
-        // Wake up neighbouring chunk if we're within 2 spaces of it (and not on
-        // the edge)
-        let wake_range = 1;
-        if local_x < wake_range && chunk_x != 0 {
-            self.chunk_grid[(chunk_x - 1, chunk_y)].update_next_frame = true;
-        } else if local_x >= self.chunk_size - wake_range
-            && chunk_x != (self.width / self.chunk_size) - 1
-        {
-            self.chunk_grid[(chunk_x + 1, chunk_y)].update_next_frame = true;
+    fn wake_chunk_from_local(&self, chunk_xy: (usize, usize), local_xy: (usize, usize)) {
+        // Wake everything within WAKE_MARGIN of the particle, in whichever
+        // chunks that covers. Usually that's just its own.
+        let inside = |n: usize| n >= WAKE_MARGIN && n + WAKE_MARGIN < self.chunk_size;
+        if inside(local_xy.0) && inside(local_xy.1) {
+            self.chunk_grid[chunk_xy].dirty_next_frame.grow(DirtyRect {
+                min: (local_xy.0 - WAKE_MARGIN, local_xy.1 - WAKE_MARGIN),
+                max: (local_xy.0 + WAKE_MARGIN, local_xy.1 + WAKE_MARGIN),
+            });
+            return;
         }
+        let (x, y) = self.chunk_xy_to_global_xy(chunk_xy, local_xy);
+        let min = (x.saturating_sub(WAKE_MARGIN), y.saturating_sub(WAKE_MARGIN));
+        let max = (
+            (x + WAKE_MARGIN).min(self.width - 1),
+            (y + WAKE_MARGIN).min(self.height - 1),
+        );
 
-        if local_y < wake_range && chunk_y != 0 {
-            self.chunk_grid[(chunk_x, chunk_y - 1)].update_next_frame = true;
-        } else if local_y >= self.chunk_size - wake_range
-            && chunk_y != (self.height / self.chunk_size) - 1
-        {
-            self.chunk_grid[(chunk_x, chunk_y + 1)].update_next_frame = true;
+        for chunk_x in (min.0 / self.chunk_size)..=(max.0 / self.chunk_size) {
+            for chunk_y in (min.1 / self.chunk_size)..=(max.1 / self.chunk_size) {
+                let (origin_x, origin_y) = self.chunk_xy_to_global_xy((chunk_x, chunk_y), (0, 0));
+                let local_rect = DirtyRect {
+                    min: (
+                        min.0.max(origin_x) - origin_x,
+                        min.1.max(origin_y) - origin_y,
+                    ),
+                    max: (
+                        max.0.min(origin_x + self.chunk_size - 1) - origin_x,
+                        max.1.min(origin_y + self.chunk_size - 1) - origin_y,
+                    ),
+                };
+                self.chunk_grid[(chunk_x, chunk_y)]
+                    .dirty_next_frame
+                    .grow(local_rect);
+            }
         }
     }
 
@@ -539,13 +1207,14 @@ impl World {
         self.get_particle(self.relative_xy(xy, dxdy))
     }
 
-    fn relative_particle_mut(&mut self, xy: (usize, usize), dxdy: (i16, i16)) -> &mut Particle {
-        self.get_particle_mut(self.relative_xy(xy, dxdy))
-    }
-
     fn relative_xy(&self, xy: (usize, usize), dxdy: (i16, i16)) -> (usize, usize) {
         // dbg!(xy, dxdy);
-        if let Some(portal) = &self.portal_grid[xy] {
+        let portal = if self.has_portals {
+            self.portal_grid[xy].as_ref()
+        } else {
+            None
+        };
+        if let Some(portal) = portal {
             if let Some(xy2) = portal.partner_xy {
                 // Might want a bit more logic to make this more comprehensive
                 if dxdy.0 != 0 && dxdy.1 != 0 {
@@ -636,3 +1305,42 @@ where
 {
     iterate_over_line_common(dxdy.0, dxdy.1, |_, _, dx, dy| inner_function(dx, dy));
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A world with a bit of everything that rolls dice: falling sand, flowing
+    // water, burning wood and a source, run for a while and saved
+    fn run(seed: u64, parallel: bool) -> Vec<u8> {
+        let mut world = World::with_seed(64, 64, 16, seed);
+        world.set_parallel(parallel);
+        for x in 8..56 {
+            world.add_new_particle(ParticleType::Sand, (x, 6), false);
+            world.add_new_particle(ParticleType::Water, (x, 30), false);
+            world.add_new_particle(ParticleType::Wood, (x, 50), false);
+        }
+        world.add_new_particle(ParticleType::Flame, (20, 49), false);
+        world.add_new_source(ParticleType::Oil, (40, 2), false, false);
+        assert_eq!(world.can_update_in_parallel(), parallel);
+
+        let mut frame = Frame::for_world(&world);
+        for _ in 0..100 {
+            world.update_all();
+            world.draw_and_refresh(&mut frame, false);
+        }
+        saved(&world)
+    }
+
+    #[test]
+    fn same_seed_same_world() {
+        assert!(run(1, false) == run(1, false));
+        assert!(run(1, false) != run(2, false));
+    }
+
+    #[test]
+    fn same_seed_same_world_in_parallel() {
+        assert!(run(1, true) == run(1, true));
+        assert!(run(1, true) != run(2, true));
+    }
+}
@@ -0,0 +1,162 @@
+//! Explosions: burning explosives that are packed tightly enough blow up,
+//! and the blast is cast out from the middle as rays, one to every cell on
+//! the edge of the square around it.
+//!
+//! Each ray starts out with the explosion's power, which falls off the
+//! further it gets. Whatever it passes through on the way decides what
+//! happens next: loose particles get thrown outwards, anything that can't
+//! get out of the way breaks if its durability is less than what's left, and
+//! anything it can't break stops it, so walls shield whatever's behind them.
+
+use super::*;
+use std::collections::HashSet;
+
+// Chance of empty space catching light, at full power
+const FLAME_CHANCE: f32 = 0.6;
+// Power kept after throwing a loose particle out of the way
+const THROW_DAMPING: f32 = 0.9;
+
+impl World {
+    pub(super) fn detonate_explosions(&mut self) {
+        let num_chunks_x = self.width / self.chunk_size;
+        let num_chunks_y = self.height / self.chunk_size;
+
+        // In a fixed order, so a replay sets them off the same way
+        for chunk_y in 0..num_chunks_y {
+            for chunk_x in 0..num_chunks_x {
+                let explosions = self.chunk_grid[(chunk_x, chunk_y)].take_explosions();
+                for (xy, explosion) in explosions {
+                    self.detonate(xy, explosion);
+                }
+            }
+        }
+    }
+
+    fn detonate(&mut self, xy: (usize, usize), explosion: Explosion) {
+        let flame = Particle::new(ParticleType::Flame, &mut self.rng);
+        self.put_particle(xy, flame);
+
+        // Cells near the middle are on lots of rays, but only get blown up
+        // by the first one to reach them
+        let mut hit = HashSet::from([xy]);
+        let reach = explosion.radius.ceil() as isize;
+        let (x, y) = (xy.0 as isize, xy.1 as isize);
+        let clamp = |x: isize, y: isize| {
+            (
+                x.clamp(0, self.width as isize - 1) as usize,
+                y.clamp(0, self.height as isize - 1) as usize,
+            )
+        };
+        let mut edge = vec![];
+        for i in -reach..=reach {
+            edge.push(clamp(x + i, y - reach));
+            edge.push(clamp(x + i, y + reach));
+            edge.push(clamp(x - reach, y + i));
+            edge.push(clamp(x + reach, y + i));
+        }
+
+        for end in edge {
+            let mut ray = vec![];
+            iterate_over_line(xy, end, |x, y| ray.push((x, y)));
+            self.cast_ray(xy, &ray, explosion, &mut hit);
+        }
+    }
+
+    fn cast_ray(
+        &mut self,
+        origin: (usize, usize),
+        ray: &[(usize, usize)],
+        explosion: Explosion,
+        hit: &mut HashSet<(usize, usize)>,
+    ) {
+        let mut lost = 0.0;
+        for (i, &xy) in ray.iter().enumerate() {
+            let dx = xy.0 as f32 - origin.0 as f32;
+            let dy = xy.1 as f32 - origin.1 as f32;
+            let distance = dx.hypot(dy);
+            if distance > explosion.radius {
+                return;
+            }
+            let energy = explosion.power * (1.0 - distance / explosion.radius) - lost;
+            if energy <= 0.0 {
+                return;
+            }
+
+            let particle = *self.get_particle(xy);
+            let properties = particle.particle_type.properties();
+            if !hit.insert(xy) {
+                // Already dealt with; anything still standing there held
+                if particle.particle_type == ParticleType::Empty
+                    || particle.is_burning()
+                    || properties.moves
+                {
+                    continue;
+                }
+                return;
+            }
+
+            if particle.particle_type == ParticleType::Empty {
+                if self.rng.gen::<f32>() < FLAME_CHANCE * energy / explosion.power {
+                    let flame = Particle::new(ParticleType::Flame, &mut self.rng);
+                    self.put_particle(xy, flame);
+                }
+            } else if particle.particle_type == ParticleType::Border {
+                return;
+            } else if properties.explodes.is_some() || particle.is_burning() {
+                // Set off the rest of the charge
+                self.get_particle_mut(xy).set_burning(true);
+            } else {
+                if properties.moves {
+                    let throw = (explosion.radius * energy / explosion.power).ceil() as usize;
+                    if let Some(landed) = self.throw_particle(xy, &ray[i + 1..], throw, (dx, dy)) {
+                        hit.insert(landed);
+                        lost += energy * (1.0 - THROW_DAMPING);
+                        continue;
+                    }
+                }
+                // Nowhere for it to go, so it has to break or hold
+                let durability = self.get_extras(xy).durability;
+                if properties.base_durability.is_none() || durability as f32 >= energy {
+                    return;
+                }
+                lost += durability as f32;
+                let debris = if self.rng.gen::<f32>() < energy / explosion.power {
+                    ParticleType::Flame
+                } else {
+                    ParticleType::Empty
+                };
+                let debris = Particle::new(debris, &mut self.rng);
+                self.put_particle(xy, debris);
+            }
+        }
+    }
+
+    // Moves the particle at xy as far as it can get along the rest of the
+    // ray, up to `throw` cells, giving it some speed in the direction it was
+    // thrown. Returns where it ended up, if it moved.
+    fn throw_particle(
+        &mut self,
+        xy: (usize, usize),
+        ray: &[(usize, usize)],
+        throw: usize,
+        direction: (f32, f32),
+    ) -> Option<(usize, usize)> {
+        let landing = ray
+            .iter()
+            .take(throw)
+            .take_while(|&&xy| self.get_particle(xy).particle_type == ParticleType::Empty)
+            .last()
+            .copied()?;
+
+        let mut particle = *self.get_particle(xy);
+        let extras = *self.get_extras(xy);
+        let length = direction.0.hypot(direction.1).max(1.0);
+        let speed = throw as f32 / length;
+        particle.set_velocity((direction.0 * speed, direction.1 * speed));
+        let empty = Particle::new(ParticleType::Empty, &mut self.rng);
+        self.put_particle(xy, empty);
+        self.put_particle(landing, particle);
+        self.put_extras(landing, extras);
+        Some(landing)
+    }
+}
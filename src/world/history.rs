@@ -0,0 +1,381 @@
+//! Undo/redo for edits made to a world from outside the simulation (placing
+//! particles, sources, sinks, portals and fans).
+//!
+//! Everything changed between `World::begin_edit` and `World::end_edit` is one
+//! edit, e.g. one brush stroke. The first time an edit touches a position, the
+//! position is remembered as it was; when the edit ends, it's remembered as it
+//! is now. Undoing puts back the before, redoing the after. Anything the
+//! simulation did to those positions in the meantime is overwritten.
+//!
+//! Rigid bodies made during an edit are remembered by the cells they were
+//! made from. Undoing lets go of every body's cells that get put back, and
+//! redoing makes the bodies again.
+
+use super::*;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// How much memory the history keeps edits in before forgetting the oldest
+/// ones, unless changed with `World::set_history_budget`.
+pub const DEFAULT_HISTORY_BUDGET: usize = 32 * 1024 * 1024;
+
+// Everything an edit can change about one position in the world
+#[derive(Clone)]
+struct Cell {
+    particle: Particle,
+    extras: ParticleExtras,
+    source: Option<ParticleSource>,
+    portal: Option<Portal>,
+    fan: Option<Fan>,
+}
+
+struct Edit {
+    // (position, before, after)
+    cells: Vec<((usize, usize), Cell, Cell)>,
+    // Everything given to add_rigid_body, in order
+    rigid_cells: Vec<(usize, usize)>,
+}
+
+impl Edit {
+    fn size(&self) -> usize {
+        self.cells.len() * std::mem::size_of::<((usize, usize), Cell, Cell)>()
+            + self.rigid_cells.len() * std::mem::size_of::<(usize, usize)>()
+    }
+}
+
+// The edit being made
+#[derive(Default)]
+struct CurrentEdit {
+    before: HashMap<(usize, usize), Cell>,
+    rigid_cells: Vec<(usize, usize)>,
+}
+
+pub(super) struct EditHistory {
+    current: Option<CurrentEdit>,
+    undo: VecDeque<Edit>,
+    redo: Vec<Edit>,
+    // Size of everything in undo and redo
+    size: usize,
+    budget: usize,
+}
+
+impl EditHistory {
+    pub(super) fn new() -> Self {
+        Self {
+            current: None,
+            undo: VecDeque::new(),
+            redo: vec![],
+            size: 0,
+            budget: DEFAULT_HISTORY_BUDGET,
+        }
+    }
+
+    pub(super) fn clear(&mut self) {
+        *self = Self {
+            budget: self.budget,
+            ..Self::new()
+        };
+    }
+
+    fn forget_oldest(&mut self) {
+        while self.size > self.budget {
+            if let Some(edit) = self.undo.pop_front() {
+                self.size -= edit.size();
+            } else if let Some(edit) = self.redo.pop() {
+                self.size -= edit.size();
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+impl World {
+    /// Start recording an edit. Anything already being recorded is finished
+    /// first.
+    pub fn begin_edit(&mut self) {
+        self.record_action(EditAction::BeginEdit);
+        self.finish_edit();
+        self.history.current = Some(CurrentEdit::default());
+    }
+
+    /// Finish recording the current edit and make it the one `undo` undoes.
+    /// Edits that didn't touch anything are dropped.
+    pub fn end_edit(&mut self) {
+        if self.history.current.is_some() {
+            self.record_action(EditAction::EndEdit);
+            self.finish_edit();
+        }
+    }
+
+    fn finish_edit(&mut self) {
+        let Some(current) = self.history.current.take() else {
+            return;
+        };
+        if current.before.is_empty() {
+            return;
+        }
+
+        let edit = Edit {
+            cells: current
+                .before
+                .into_iter()
+                .map(|(xy, before)| (xy, before, self.cell(xy)))
+                .collect(),
+            rigid_cells: current.rigid_cells,
+        };
+
+        let history = &mut self.history;
+        for edit in history.redo.drain(..) {
+            history.size -= edit.size();
+        }
+        history.size += edit.size();
+        history.undo.push_back(edit);
+        history.forget_oldest();
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.history.undo.is_empty()
+            || self
+                .history
+                .current
+                .as_ref()
+                .is_some_and(|c| !c.before.is_empty())
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.history.redo.is_empty()
+    }
+
+    /// Put back everything the last edit changed. Returns false if there was
+    /// nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        self.record_action(EditAction::Undo);
+        self.finish_edit();
+        let Some(edit) = self.history.undo.pop_back() else {
+            return false;
+        };
+        for (xy, before, _) in edit.cells.iter() {
+            self.restore_cell(*xy, before);
+        }
+        let restored: HashSet<(usize, usize)> = edit.cells.iter().map(|c| c.0).collect();
+        self.release_rigid_cells(&restored);
+        self.history.redo.push(edit);
+        self.recheck_portals();
+        true
+    }
+
+    /// Make the last undone edit again. Returns false if there was nothing to
+    /// redo.
+    pub fn redo(&mut self) -> bool {
+        self.record_action(EditAction::Redo);
+        self.finish_edit();
+        let Some(edit) = self.history.redo.pop() else {
+            return false;
+        };
+        for (xy, _, after) in edit.cells.iter() {
+            self.restore_cell(*xy, after);
+        }
+        let restored: HashSet<(usize, usize)> = edit.cells.iter().map(|c| c.0).collect();
+        self.release_rigid_cells(&restored);
+        self.make_rigid_bodies(&edit.rigid_cells);
+        self.history.undo.push_back(edit);
+        self.recheck_portals();
+        true
+    }
+
+    /// Limit how much memory (roughly, in bytes) undo/redo history can use.
+    /// The oldest edits are forgotten first; a single edit bigger than the
+    /// whole budget can't be undone at all.
+    pub fn set_history_budget(&mut self, bytes: usize) {
+        self.history.budget = bytes;
+        self.history.forget_oldest();
+    }
+
+    // Called before an edit changes anything at xy
+    pub(super) fn record_edit(&mut self, xy: (usize, usize)) {
+        match &self.history.current {
+            Some(current) if !current.before.contains_key(&xy) => {}
+            _ => return,
+        }
+        let cell = self.cell(xy);
+        if let Some(current) = self.history.current.as_mut() {
+            current.before.insert(xy, cell);
+        }
+    }
+
+    // Called when an edit makes rigid bodies out of cells
+    pub(super) fn record_rigid_edit(&mut self, cells: &[(usize, usize)]) {
+        if let Some(current) = self.history.current.as_mut() {
+            current.rigid_cells.extend_from_slice(cells);
+        }
+    }
+
+    fn cell(&self, xy: (usize, usize)) -> Cell {
+        Cell {
+            particle: *self.get_particle(xy),
+            extras: *self.get_extras(xy),
+            source: self.source_grid[xy].clone(),
+            portal: self.portal_grid[xy].clone(),
+            fan: self.fan_grid[xy].clone(),
+        }
+    }
+
+    fn restore_cell(&mut self, xy: (usize, usize), cell: &Cell) {
+        let mut particle = cell.particle;
+        particle.refresh();
+        self.put_particle(xy, particle);
+        self.put_extras(xy, cell.extras);
+        self.source_grid[xy] = cell.source.clone();
+        self.portal_grid[xy] = cell.portal.clone();
+        self.fan_grid[xy] = cell.fan.clone();
+    }
+
+    fn recheck_portals(&mut self) {
+        self.has_portals = self
+            .portal_grid
+            .elements_row_major_iter()
+            .any(Option::is_some);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Everything in every cell, which undo and redo should put back (unlike
+    // the random number generator, which carries on from where it got to)
+    fn grid(world: &World) -> Vec<u8> {
+        let mut cells = vec![];
+        for y in 0..world.height {
+            for x in 0..world.width {
+                let xy = (x, y);
+                cells.push((
+                    SavedParticle::new(*world.get_particle(xy), *world.get_extras(xy)),
+                    &world.source_grid[xy],
+                    &world.portal_grid[xy],
+                    &world.fan_grid[xy],
+                ));
+            }
+        }
+        bincode::serialize(&cells).unwrap()
+    }
+
+    // Something already going on in the world for the edits to land in
+    fn settled_world() -> World {
+        let mut world = World::with_seed(32, 32, 8, 5);
+        for x in 1..31 {
+            for y in 20..31 {
+                world.add_new_particle(ParticleType::Water, (x, y), false);
+            }
+            world.add_new_particle(ParticleType::Wood, (x, 12), false);
+        }
+        // Worn down, so that putting it back has to bring its durability too
+        let mut extras = *world.get_extras((10, 12));
+        extras.durability = 3;
+        world.put_extras((10, 12), extras);
+        let mut frame = Frame::for_world(&world);
+        for _ in 0..30 {
+            world.update_all();
+            world.draw_and_refresh(&mut frame, false);
+        }
+        world
+    }
+
+    // A brush stroke through the water and wood, plus one of everything else
+    fn edit(world: &mut World) {
+        world.begin_edit();
+        for x in 4..28 {
+            for y in 10..24 {
+                world.add_new_particle(ParticleType::Sand, (x, y), true);
+            }
+        }
+        world.add_new_source(ParticleType::Oil, (3, 3), false, false);
+        world.add_new_fan((28, 5), Direction::Left, 2, false);
+        let color = PColor::new(0, 0, 255);
+        world.add_new_portal((5, 6), None, Direction::Up, color);
+        world.add_new_portal((20, 6), Some((5, 6)), Direction::Up, color);
+        world.end_edit();
+    }
+
+    #[test]
+    fn undo_then_redo_restores_exactly() {
+        let mut world = settled_world();
+        let before = grid(&world);
+        edit(&mut world);
+        let after = grid(&world);
+        assert!(after != before);
+
+        assert!(world.undo());
+        assert!(grid(&world) == before);
+        assert!(!world.has_portals);
+        assert!(world.redo());
+        assert!(grid(&world) == after);
+        assert!(world.has_portals);
+        assert!(world.undo());
+        assert!(grid(&world) == before);
+    }
+
+    #[test]
+    fn edits_undo_in_reverse_order() {
+        let mut world = settled_world();
+        let first = grid(&world);
+        world.begin_edit();
+        world.add_new_particle(ParticleType::Sand, (10, 5), false);
+        world.end_edit();
+        let second = grid(&world);
+        edit(&mut world);
+
+        assert!(world.undo());
+        assert!(grid(&world) == second);
+        assert!(world.undo());
+        assert!(grid(&world) == first);
+        assert!(!world.can_undo());
+        assert!(!world.undo());
+
+        // A new edit after undoing throws away what could have been redone
+        assert!(world.can_redo());
+        world.begin_edit();
+        world.add_new_particle(ParticleType::Salt, (10, 5), false);
+        world.end_edit();
+        assert!(!world.can_redo());
+    }
+
+    #[test]
+    fn undo_and_redo_take_rigid_bodies_with_them() {
+        let mut world = settled_world();
+        let before = grid(&world);
+        world.begin_edit();
+        let cells: Vec<_> = (10..14).flat_map(|x| (3..5).map(move |y| (x, y))).collect();
+        for &xy in &cells {
+            world.add_new_particle(ParticleType::Concrete, xy, false);
+        }
+        world.add_rigid_body(&cells);
+        world.end_edit();
+        let after = grid(&world);
+        assert_eq!(world.rigid_body_count(), 1);
+
+        assert!(world.undo());
+        assert!(grid(&world) == before);
+        assert_eq!(world.rigid_body_count(), 0);
+        assert!(world.redo());
+        assert!(grid(&world) == after);
+        assert_eq!(world.rigid_body_count(), 1);
+    }
+
+    #[test]
+    fn budget_forgets_oldest_edits() {
+        let mut world = settled_world();
+        world.begin_edit();
+        world.add_new_particle(ParticleType::Sand, (10, 5), false);
+        world.end_edit();
+        edit(&mut world);
+        let one_edit = world.history.undo[1].size();
+        world.set_history_budget(one_edit);
+
+        assert!(world.undo());
+        assert!(!world.can_undo());
+
+        world.set_history_budget(0);
+        assert!(!world.can_redo());
+    }
+}
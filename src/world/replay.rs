@@ -0,0 +1,412 @@
+//! Recording every edit made to a world, tagged with the tick it was made on,
+//! so that a session can be played back exactly.
+//!
+//! A replay file is the magic bytes `SREP`, a little-endian `u32` format
+//! version, then the bincode-encoded body. The body holds the world as it was
+//! when recording started (as a world file, so with its generator's state) and
+//! the edits made to it since, as the `World` methods that were called rather
+//! than what the mouse did, so playing one back doesn't depend on the app.
+
+use super::*;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"SREP";
+const FORMAT_VERSION: u32 = 1;
+
+/// One call to one of the `World` methods that edit it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum EditAction {
+    AddParticle {
+        particle_type: ParticleType,
+        xy: (usize, usize),
+        replace: bool,
+    },
+    AddSource {
+        particle_type: ParticleType,
+        xy: (usize, usize),
+        source_replaces: bool,
+        replace: bool,
+    },
+    DeleteSource {
+        xy: (usize, usize),
+    },
+    AddPortal {
+        xy: (usize, usize),
+        partner_xy: Option<(usize, usize)>,
+        direction: Direction,
+        color: PColor,
+    },
+    BeginEdit,
+    EndEdit,
+    Undo,
+    Redo,
+    SetParallel(bool),
+    AddRigidBody {
+        cells: Vec<(usize, usize)>,
+    },
+    AddFan {
+        xy: (usize, usize),
+        direction: Direction,
+        strength: u8,
+        replace: bool,
+    },
+    DeleteFan {
+        xy: (usize, usize),
+    },
+    SetWind(i8),
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Replay {
+    // Labels of the particle types the actions use, like in a world file
+    materials: Vec<String>,
+    // The world when recording started, as a world file
+    world: Vec<u8>,
+    parallel: bool,
+    // Ticks since recording started, and what was done before that tick's
+    // update. In order.
+    actions: Vec<(u64, EditAction)>,
+    ticks: u64,
+}
+
+impl std::fmt::Debug for Replay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Replay")
+            .field("ticks", &self.ticks)
+            .field("actions", &self.actions.len())
+            .finish()
+    }
+}
+
+impl Replay {
+    /// How many ticks the session ran for.
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    /// The world as it was when the session started.
+    pub fn world(&self) -> Result<World, SaveError> {
+        let mut world = World::load(self.world.as_slice())?;
+        world.set_parallel(self.parallel);
+
+        let in_bounds = |xy: &(usize, usize)| xy.0 < world.width && xy.1 < world.height;
+        // A portal's partner is always the one placed just before it, and
+        // the app forgets that one on undo or redo, so it has to have been
+        // placed since the last of those (or be there from the start)
+        let mut portals: HashSet<(usize, usize)> = (0..world.width)
+            .flat_map(|x| (0..world.height).map(move |y| (x, y)))
+            .filter(|&xy| world.portal_exists_at(xy))
+            .collect();
+        for (_, action) in &self.actions {
+            let valid = match action {
+                EditAction::AddParticle { xy, .. }
+                | EditAction::AddSource { xy, .. }
+                | EditAction::DeleteSource { xy }
+                | EditAction::AddFan { xy, .. }
+                | EditAction::DeleteFan { xy } => in_bounds(xy),
+                EditAction::AddPortal { xy, partner_xy, .. } => {
+                    if partner_xy.is_some_and(|p| !portals.contains(&p)) {
+                        return Err(SaveError::Invalid("portal partner doesn't exist"));
+                    }
+                    portals.insert(*xy);
+                    in_bounds(xy)
+                }
+                EditAction::AddRigidBody { cells } => cells.iter().all(in_bounds),
+                EditAction::Undo | EditAction::Redo => {
+                    portals.clear();
+                    true
+                }
+                _ => true,
+            };
+            if !valid {
+                return Err(SaveError::Invalid("edit out of bounds"));
+            }
+        }
+        Ok(world)
+    }
+
+    /// Make the edits that were made before update number `tick`. Playing back
+    /// a session is calling this then `World::update_all` for every tick, then
+    /// calling this once more for the edits made after the last update.
+    ///
+    /// Like in the app, `World::draw_and_refresh` needs calling before every
+    /// update too.
+    pub fn apply_edits(&self, world: &mut World, tick: u64) {
+        let start = self.actions.partition_point(|(t, _)| *t < tick);
+        for (_, action) in self.actions[start..].iter().take_while(|(t, _)| *t == tick) {
+            world.apply_edit_action(action.clone());
+        }
+    }
+
+    /// Play the whole session back, returning the world as it ended up.
+    pub fn play<R: Renderer>(&self, renderer: &mut R) -> Result<World, SaveError> {
+        let mut world = self.world()?;
+        for tick in 0..self.ticks {
+            self.apply_edits(&mut world, tick);
+            world.draw_and_refresh(renderer, false);
+            world.update_all();
+        }
+        self.apply_edits(&mut world, self.ticks);
+        Ok(world)
+    }
+
+    pub fn save<W: Write>(&self, mut writer: W) -> Result<(), SaveError> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        bincode::serialize_into(&mut writer, self)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn load<R: Read>(mut reader: R) -> Result<Replay, SaveError> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(SaveError::NotAReplayFile);
+        }
+
+        let mut version = [0; 4];
+        reader.read_exact(&mut version)?;
+        let version = u32::from_le_bytes(version);
+        if version != FORMAT_VERSION {
+            return Err(SaveError::UnsupportedReplayVersion(version));
+        }
+
+        let mut replay: Replay = bincode::deserialize_from(reader)?;
+        replay.remap_particle_types()?;
+        Ok(replay)
+    }
+
+    pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), SaveError> {
+        self.save(BufWriter::new(File::create(path)?))
+    }
+
+    pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Replay, SaveError> {
+        Replay::load(BufReader::new(File::open(path)?))
+    }
+
+    fn remap_particle_types(&mut self) -> Result<(), SaveError> {
+        let remap = self
+            .materials
+            .iter()
+            .map(|label| {
+                ParticleType::from_label(label)
+                    .ok_or_else(|| SaveError::UnknownMaterial(label.clone()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for (_, action) in self.actions.iter_mut() {
+            if let EditAction::AddParticle { particle_type, .. }
+            | EditAction::AddSource { particle_type, .. } = action
+            {
+                *particle_type = *remap
+                    .get(particle_type.0 as usize)
+                    .ok_or(SaveError::Invalid("particle type out of range"))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl World {
+    /// Start recording every edit made to the world, to play back later.
+    /// Anything already being recorded is thrown away, and so is the undo
+    /// history, since the replay couldn't undo edits from before it started.
+    pub fn start_recording_edits(&mut self) -> Result<(), SaveError> {
+        let mut world = vec![];
+        self.save(&mut world)?;
+        // A loaded world starts with everything awake, so this one has to too
+        // for the two to carry on the same way
+        self.wake_everything();
+        self.history.clear();
+        self.replay = Some(Replay {
+            materials: ParticleType::all()
+                .map(|t| t.properties().label.clone())
+                .collect(),
+            world,
+            parallel: self.parallel,
+            actions: vec![],
+            ticks: 0,
+        });
+        Ok(())
+    }
+
+    pub fn stop_recording_edits(&mut self) -> Option<Replay> {
+        self.replay.take()
+    }
+
+    pub fn is_recording_edits(&self) -> bool {
+        self.replay.is_some()
+    }
+
+    // Called by every method that edits the world
+    pub(super) fn record_action(&mut self, action: EditAction) {
+        if let Some(replay) = self.replay.as_mut() {
+            replay.actions.push((replay.ticks, action));
+        }
+    }
+
+    pub(super) fn record_tick(&mut self) {
+        if let Some(replay) = self.replay.as_mut() {
+            replay.ticks += 1;
+        }
+    }
+
+    fn apply_edit_action(&mut self, action: EditAction) {
+        match action {
+            EditAction::AddParticle {
+                particle_type,
+                xy,
+                replace,
+            } => {
+                self.add_new_particle(particle_type, xy, replace);
+            }
+            EditAction::AddSource {
+                particle_type,
+                xy,
+                source_replaces,
+                replace,
+            } => self.add_new_source(particle_type, xy, source_replaces, replace),
+            EditAction::DeleteSource { xy } => self.delete_source(xy),
+            EditAction::AddPortal {
+                xy,
+                partner_xy,
+                direction,
+                color,
+            } => {
+                self.add_new_portal(xy, partner_xy, direction, color);
+            }
+            EditAction::BeginEdit => self.begin_edit(),
+            EditAction::EndEdit => self.end_edit(),
+            EditAction::Undo => {
+                self.undo();
+            }
+            EditAction::Redo => {
+                self.redo();
+            }
+            EditAction::SetParallel(parallel) => self.set_parallel(parallel),
+            EditAction::AddRigidBody { cells } => self.add_rigid_body(&cells),
+            EditAction::AddFan {
+                xy,
+                direction,
+                strength,
+                replace,
+            } => self.add_new_fan(xy, direction, strength, replace),
+            EditAction::DeleteFan { xy } => self.delete_fan(xy),
+            EditAction::SetWind(wind) => self.set_wind(wind),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A session in the app: edits between ticks, one frame drawn per tick
+    fn session(world: &mut World, frame: &mut Frame) {
+        for tick in 0..120 {
+            match tick {
+                5 => {
+                    world.begin_edit();
+                    for x in 10..40 {
+                        world.add_new_particle(ParticleType::Sand, (x, 5), false);
+                    }
+                    world.end_edit();
+                }
+                20 => {
+                    world.begin_edit();
+                    world.add_new_source(ParticleType::Water, (30, 2), false, false);
+                    world.add_new_fan((2, 40), Direction::Right, 2, false);
+                    world.end_edit();
+                }
+                30 => {
+                    world.begin_edit();
+                    let cells: Vec<_> = (20..26)
+                        .flat_map(|x| (10..13).map(move |y| (x, y)))
+                        .collect();
+                    for &xy in &cells {
+                        world.add_new_particle(ParticleType::Concrete, xy, true);
+                    }
+                    world.add_rigid_body(&cells);
+                    world.end_edit();
+                }
+                45 => world.set_wind(2),
+                50 => {
+                    world.undo();
+                }
+                55 => {
+                    world.redo();
+                }
+                60 => world.set_parallel(true),
+                80 => {
+                    world.begin_edit();
+                    world.delete_source((30, 2));
+                    world.add_new_particle(ParticleType::Flame, (12, 60), false);
+                    world.end_edit();
+                }
+                _ => {}
+            }
+            world.draw_and_refresh(frame, false);
+            world.update_all();
+        }
+        // And one more after the last tick
+        world.begin_edit();
+        world.add_new_particle(ParticleType::Salt, (50, 3), false);
+        world.end_edit();
+    }
+
+    #[test]
+    fn replay_reproduces_the_session() {
+        let mut world = World::with_seed(64, 64, 16, 11);
+        for x in 1..63 {
+            for y in 50..63 {
+                world.add_new_particle(ParticleType::Water, (x, y), false);
+            }
+        }
+        // Dry fungus rolls dice whenever it's updated, but doesn't keep
+        // itself awake
+        for x in 40..60 {
+            for y in 20..24 {
+                world.add_new_particle(ParticleType::Fungus, (x, y), false);
+            }
+        }
+        let mut frame = Frame::for_world(&world);
+        // Already running, so that not everything is awake when recording
+        // starts
+        for _ in 0..40 {
+            world.draw_and_refresh(&mut frame, false);
+            world.update_all();
+        }
+
+        world.start_recording_edits().unwrap();
+        session(&mut world, &mut frame);
+        let replay = world.stop_recording_edits().unwrap();
+        assert_eq!(replay.ticks(), 120);
+
+        let mut bytes = vec![];
+        replay.save(&mut bytes).unwrap();
+        let replay = Replay::load(bytes.as_slice()).unwrap();
+        let played = replay.play(&mut frame).unwrap();
+        assert!(saved(&played) == saved(&world));
+    }
+
+    #[test]
+    fn rejects_edits_outside_the_world() {
+        let mut world = World::with_seed(16, 16, 8, 0);
+        world.start_recording_edits().unwrap();
+        world.update_all();
+        let mut replay = world.stop_recording_edits().unwrap();
+        replay.actions.push((
+            1,
+            EditAction::AddParticle {
+                particle_type: ParticleType::Sand,
+                xy: (3, 40),
+                replace: false,
+            },
+        ));
+        assert!(matches!(replay.world(), Err(SaveError::Invalid(_))));
+    }
+}
@@ -0,0 +1,520 @@
+//! Rigid bodies: connected groups of solid particles that fall, tip over and
+//! land as one.
+//!
+//! A body's particles stay in the particle grid like any other, so they still
+//! burn, conduct heat and get drawn; the body just remembers which cells are
+//! its own and moves them all together once the particles have updated. A
+//! cell stops being part of its body when the simulation turns it into
+//! something that isn't solid any more (burns away, dissolves, melts).
+//!
+//! A body that hasn't moved for a while goes to sleep, and isn't stepped again
+//! until something near it changes.
+
+use super::*;
+use std::collections::HashSet;
+
+// In cells per tick (per tick)
+const GRAVITY: f32 = 0.5;
+const MAX_SPEED: f32 = 8.0;
+// Sideways speed kept each tick spent resting on something
+const FRICTION: f32 = 0.8;
+// Speed kept for each cell of fluid pushed out of the way
+const FLUID_DRAG: f32 = 0.95;
+// How quickly something hanging over an edge starts to tip, in radians per
+// tick (per tick)
+const TIP_RATE: f32 = 0.02;
+const MAX_SPIN: f32 = 0.2;
+const SPIN_DAMPING: f32 = 0.9;
+// Landings faster than this wear away the body's durability, by DAMAGE for
+// every cell per tick over it. Once any of its particles runs out, the body
+// shatters.
+const SAFE_IMPACT: f32 = 3.0;
+const DAMAGE: f32 = 25.0;
+// Ticks a body has to spend without moving before it goes to sleep
+const SLEEP_AFTER: u8 = 30;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(super) struct RigidBody {
+    // Where each cell sits relative to the body's position, unrotated
+    offsets: Vec<(i16, i16)>,
+    // Where each cell is in the world now, in the same order
+    cells: Vec<(usize, usize)>,
+    position: (f32, f32),
+    velocity: (f32, f32),
+    angle: f32,
+    spin: f32,
+    // Ticks spent without moving, up to SLEEP_AFTER. Not saved: bodies all
+    // wake up when a world is loaded and settle again.
+    #[serde(skip)]
+    resting: u8,
+}
+
+impl RigidBody {
+    fn new(cells: Vec<(usize, usize)>) -> Self {
+        // Rotate around the cell nearest the middle
+        let n = cells.len() as f32;
+        let anchor = (
+            (cells.iter().map(|c| c.0 as f32).sum::<f32>() / n).round(),
+            (cells.iter().map(|c| c.1 as f32).sum::<f32>() / n).round(),
+        );
+        Self {
+            offsets: cells
+                .iter()
+                .map(|&(x, y)| ((x as f32 - anchor.0) as i16, (y as f32 - anchor.1) as i16))
+                .collect(),
+            cells,
+            position: anchor,
+            velocity: (0.0, 0.0),
+            angle: 0.0,
+            spin: 0.0,
+            resting: 0,
+        }
+    }
+
+    // Keep only the cells where `keep` is true
+    fn retain_cells(&mut self, keep: &[bool]) {
+        if keep.contains(&false) {
+            let mut keep_iter = keep.iter();
+            self.offsets.retain(|_| *keep_iter.next().unwrap());
+            let mut keep_iter = keep.iter();
+            self.cells.retain(|_| *keep_iter.next().unwrap());
+        }
+    }
+
+    pub(super) fn in_bounds(&self, width: usize, height: usize) -> bool {
+        self.offsets.len() == self.cells.len()
+            && self.cells.iter().all(|&(x, y)| x < width && y < height)
+    }
+
+    /// Which cells the body would cover at `position` and `angle`. Rotating
+    /// can round two cells onto the same one, in which case the second goes
+    /// next to it instead.
+    fn cells_at(&self, position: (f32, f32), angle: f32) -> Option<Vec<(i64, i64)>> {
+        let (sin, cos) = angle.sin_cos();
+        let origin = (position.0.round() as i64, position.1.round() as i64);
+        let mut taken = HashSet::with_capacity(self.offsets.len());
+        let mut cells = Vec::with_capacity(self.offsets.len());
+
+        for &(ox, oy) in self.offsets.iter() {
+            let (ox, oy) = (ox as f32, oy as f32);
+            let (x, y) = (ox * cos - oy * sin, ox * sin + oy * cos);
+            let nearest = (origin.0 + x.round() as i64, origin.1 + y.round() as i64);
+            let cell = std::iter::once((0, 0))
+                .chain(ALL_AROUND.map(|d| (d.x as i64, d.y as i64)))
+                .map(|(dx, dy)| (nearest.0 + dx, nearest.1 + dy))
+                .find(|cell| !taken.contains(cell))?;
+            taken.insert(cell);
+            cells.push(cell);
+        }
+        Some(cells)
+    }
+}
+
+impl World {
+    /// Turn the solid particles at `cells` into rigid bodies, one for each
+    /// group of them that's connected. Particles that move on their own, or
+    /// are already part of a body, are left alone.
+    pub fn add_rigid_body(&mut self, cells: &[(usize, usize)]) {
+        self.record_action(EditAction::AddRigidBody {
+            cells: cells.to_vec(),
+        });
+        self.record_rigid_edit(cells);
+        self.make_rigid_bodies(cells);
+    }
+
+    pub(super) fn make_rigid_bodies(&mut self, cells: &[(usize, usize)]) {
+        let in_bodies: HashSet<(usize, usize)> = self
+            .rigid_bodies
+            .iter()
+            .flat_map(|body| body.cells.iter().copied())
+            .collect();
+        let mut remaining: HashSet<(usize, usize)> = cells
+            .iter()
+            .copied()
+            .filter(|&xy| {
+                xy.0 < self.width
+                    && xy.1 < self.height
+                    && self.is_solid(xy)
+                    && !in_bodies.contains(&xy)
+            })
+            .collect();
+
+        // Flood fill, in the order the cells were given so the bodies come out
+        // the same every time
+        for &start in cells {
+            if !remaining.remove(&start) {
+                continue;
+            }
+            let mut group = vec![start];
+            let mut i = 0;
+            while i < group.len() {
+                let (x, y) = group[i];
+                for d in ORTHOGONAL {
+                    let next = (
+                        x.wrapping_add_signed(d.x as isize),
+                        y.wrapping_add_signed(d.y as isize),
+                    );
+                    if remaining.remove(&next) {
+                        group.push(next);
+                    }
+                }
+                i += 1;
+            }
+            self.rigid_bodies.push(RigidBody::new(group));
+        }
+    }
+
+    // Cells at `xy` stop being part of whichever body they were in, and bodies
+    // left with nothing go
+    pub(super) fn release_rigid_cells(&mut self, xy: &HashSet<(usize, usize)>) {
+        for body in self.rigid_bodies.iter_mut() {
+            let keep: Vec<bool> = body.cells.iter().map(|c| !xy.contains(c)).collect();
+            body.retain_cells(&keep);
+        }
+        self.rigid_bodies.retain(|body| !body.cells.is_empty());
+    }
+
+    pub fn rigid_body_count(&self) -> usize {
+        self.rigid_bodies.len()
+    }
+
+    // Solid enough to be part of a body, or to stop one
+    fn is_solid(&self, xy: (usize, usize)) -> bool {
+        let particle_type = self.get_particle(xy).particle_type;
+        particle_type != ParticleType::Empty
+            && particle_type != ParticleType::Border
+            && !particle_type.properties().moves
+    }
+
+    pub(super) fn update_rigid_bodies(&mut self) {
+        let bodies = std::mem::take(&mut self.rigid_bodies);
+        for mut body in bodies {
+            if body.resting >= SLEEP_AFTER {
+                if !self.changed_near(&body) {
+                    self.rigid_bodies.push(body);
+                    continue;
+                }
+                body.resting = 0;
+            }
+            if let Some(body) = self.step_rigid_body(body) {
+                self.rigid_bodies.push(body);
+            }
+        }
+    }
+
+    // Anything changing within WAKE_MARGIN of a cell wakes it up for the next
+    // frame, so that's what counts as something touching a sleeping body
+    fn changed_near(&self, body: &RigidBody) -> bool {
+        body.cells.iter().any(|&xy| {
+            let (chunk_xy, local_xy) = self.global_xy_to_chunk_xy(xy);
+            self.chunk_grid[chunk_xy]
+                .touched_rect()
+                .is_some_and(|rect| rect.contains(local_xy))
+        })
+    }
+
+    // Returns what's left of the body afterwards: nothing if all of it's gone
+    // or it shattered
+    fn step_rigid_body(&mut self, mut body: RigidBody) -> Option<RigidBody> {
+        // Let go of anything that isn't solid any more
+        let keep: Vec<bool> = body.cells.iter().map(|&xy| self.is_solid(xy)).collect();
+        body.retain_cells(&keep);
+        if body.cells.is_empty() {
+            return None;
+        }
+        let (cells, angle) = (body.cells.clone(), body.angle);
+
+        body.velocity.1 = (body.velocity.1 + GRAVITY).min(MAX_SPEED);
+        let mut impact: f32 = 0.0;
+
+        let falling_speed = body.velocity.1;
+        let landed = self.slide_rigid_body(&mut body, (0.0, falling_speed), 1);
+        if landed {
+            impact = falling_speed.abs();
+            body.velocity.1 = 0.0;
+            // Sit exactly on whatever it landed on, so it doesn't creep down
+            // a fraction of a cell at a time and keep "landing"
+            body.position.1 = body.position.1.round();
+        }
+        let sideways_speed = body.velocity.0;
+        if self.slide_rigid_body(&mut body, (sideways_speed, 0.0), 0) {
+            impact = impact.max(sideways_speed.abs());
+            body.velocity.0 = 0.0;
+        }
+
+        if landed {
+            body.velocity.0 *= FRICTION;
+            self.tip_rigid_body(&mut body);
+        }
+        if body.spin != 0.0 {
+            let (position, angle) = (body.position, body.angle + body.spin);
+            if self
+                .try_moving_rigid_body(&mut body, position, angle)
+                .is_some()
+            {
+                body.angle = angle;
+            } else {
+                body.spin = 0.0;
+            }
+            body.spin *= SPIN_DAMPING;
+            if body.spin.abs() < TIP_RATE / 4.0 {
+                body.spin = 0.0;
+            }
+        }
+
+        if impact > SAFE_IMPACT && body.cells.len() > 1 {
+            let damage = ((impact - SAFE_IMPACT) * DAMAGE) as i16;
+            let mut broken = false;
+            for &xy in body.cells.iter() {
+                let mut extras = *self.get_extras(xy);
+                broken |= extras.wear(self.get_particle(xy).particle_type, damage);
+                self.put_extras(xy, extras);
+            }
+            if broken {
+                self.shatter_rigid_body(body, impact);
+                return None;
+            }
+        }
+
+        if body.cells == cells && body.angle == angle && body.velocity.0.abs() < 0.01 {
+            body.resting = (body.resting + 1).min(SLEEP_AFTER);
+            if body.resting == SLEEP_AFTER {
+                body.velocity = (0.0, 0.0);
+            }
+        } else {
+            body.resting = 0;
+        }
+        Some(body)
+    }
+
+    // Move the body by `delta` a cell at a time. Returns true if something got
+    // in the way. `axis` is the one delta is along, for the drag from fluids.
+    fn slide_rigid_body(&mut self, body: &mut RigidBody, delta: (f32, f32), axis: usize) -> bool {
+        let steps = delta.0.abs().max(delta.1.abs()).ceil() as usize;
+        for _ in 0..steps {
+            let position = (
+                body.position.0 + delta.0 / steps as f32,
+                body.position.1 + delta.1 / steps as f32,
+            );
+            let Some(displaced) = self.try_moving_rigid_body(body, position, body.angle) else {
+                return true;
+            };
+            if displaced > 0 {
+                let speed = if axis == 0 {
+                    &mut body.velocity.0
+                } else {
+                    &mut body.velocity.1
+                };
+                *speed *= FLUID_DRAG.powi(displaced as i32);
+            }
+        }
+        false
+    }
+
+    // Move the body to `position` and `angle` if nothing solid is in the way.
+    // Fluids where it ends up get pushed into the cells it leaves behind.
+    // Returns how many were, or None if it couldn't move.
+    fn try_moving_rigid_body(
+        &mut self,
+        body: &mut RigidBody,
+        position: (f32, f32),
+        angle: f32,
+    ) -> Option<usize> {
+        let targets = body.cells_at(position, angle)?;
+        let current: HashSet<(usize, usize)> = body.cells.iter().copied().collect();
+
+        let mut new_cells = Vec::with_capacity(targets.len());
+        for (x, y) in targets {
+            if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+                return None;
+            }
+            let xy = (x as usize, y as usize);
+            let particle_type = self.get_particle(xy).particle_type;
+            let free = particle_type == ParticleType::Empty || particle_type.properties().fluid;
+            if !current.contains(&xy) && !free {
+                return None;
+            }
+            new_cells.push(xy);
+        }
+
+        body.position = position;
+        if new_cells == body.cells {
+            return Some(0);
+        }
+
+        let targets: HashSet<(usize, usize)> = new_cells.iter().copied().collect();
+        let particles: Vec<(Particle, ParticleExtras)> = body
+            .cells
+            .iter()
+            .map(|&xy| (*self.get_particle(xy), *self.get_extras(xy)))
+            .collect();
+        let displaced: Vec<(Particle, ParticleExtras)> = new_cells
+            .iter()
+            .filter(|xy| !current.contains(xy))
+            .map(|&xy| (*self.get_particle(xy), *self.get_extras(xy)))
+            .filter(|(p, _)| p.particle_type != ParticleType::Empty)
+            .collect();
+        let vacated: Vec<(usize, usize)> = body
+            .cells
+            .iter()
+            .copied()
+            .filter(|xy| !targets.contains(xy))
+            .collect();
+
+        let empty = Particle::new(ParticleType::Empty, &mut self.rng);
+        for &xy in body.cells.iter() {
+            self.put_particle(xy, empty);
+        }
+        for (&xy, &(particle, extras)) in new_cells.iter().zip(particles.iter()) {
+            self.put_particle(xy, particle);
+            self.put_extras(xy, extras);
+        }
+        // There are always as many cells left as taken, so everything pushed
+        // out of the way fits
+        for (&xy, &(particle, extras)) in vacated.iter().zip(displaced.iter()) {
+            self.put_particle(xy, particle);
+            self.put_extras(xy, extras);
+        }
+        body.cells = new_cells;
+        Some(displaced.len())
+    }
+
+    // Start something resting on an edge tipping towards the side where more
+    // of it hangs off
+    fn tip_rigid_body(&self, body: &mut RigidBody) {
+        let own: HashSet<(usize, usize)> = body.cells.iter().copied().collect();
+        let supports = body.cells.iter().filter(|&&(x, y)| {
+            let below = (x, y + 1);
+            below.1 < self.height && !own.contains(&below) && {
+                let particle_type = self.get_particle(below).particle_type;
+                particle_type != ParticleType::Empty && !particle_type.properties().fluid
+            }
+        });
+        let Some((min_x, max_x)) = supports.fold(None, |range: Option<(usize, usize)>, &(x, _)| {
+            Some(range.map_or((x, x), |(min, max)| (min.min(x), max.max(x))))
+        }) else {
+            return;
+        };
+
+        let middle = body.cells.iter().map(|c| c.0 as f32).sum::<f32>() / body.cells.len() as f32;
+        if middle > max_x as f32 + 0.5 {
+            body.spin = (body.spin + TIP_RATE).min(MAX_SPIN);
+            body.velocity.0 += TIP_RATE;
+        } else if middle < min_x as f32 - 0.5 {
+            body.spin = (body.spin - TIP_RATE).max(-MAX_SPIN);
+            body.velocity.0 -= TIP_RATE;
+        }
+    }
+
+    // Let go of every cell as a loose particle, flying apart from where the
+    // body landed. Anything that still can't move stays where it broke.
+    fn shatter_rigid_body(&mut self, body: RigidBody, impact: f32) {
+        let middle = (
+            body.cells.iter().map(|c| c.0 as f32).sum::<f32>() / body.cells.len() as f32,
+            body.cells.iter().map(|c| c.1 as f32).sum::<f32>() / body.cells.len() as f32,
+        );
+        for xy in body.cells {
+            let mut shard = *self.get_particle(xy);
+            if let Some(becomes) = shard.particle_type.properties().shatters_into {
+                let temperature = shard.temperature();
+                shard = Particle::new(becomes, &mut self.rng);
+                shard.set_temperature(temperature);
+            }
+            if shard.particle_type.properties().moves {
+                let spread = ((xy.0 as f32 - middle.0) * 0.5).clamp(-2.0, 2.0);
+                shard.set_velocity((body.velocity.0 + spread, body.velocity.1 - impact * 0.25));
+            }
+            self.put_particle(xy, shard);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cells_of(world: &World, particle_type: ParticleType) -> Vec<(usize, usize)> {
+        let mut cells = vec![];
+        for y in 0..world.height {
+            for x in 0..world.width {
+                if world.get_particle((x, y)).particle_type == particle_type {
+                    cells.push((x, y));
+                }
+            }
+        }
+        cells
+    }
+
+    // A 4x3 block of concrete made into a body, `drop` cells above the floor
+    fn dropped_block(world: &mut World, drop: usize) -> Vec<(usize, usize)> {
+        let top = world.height - 1 - drop - 3;
+        let cells: Vec<_> = (10..14)
+            .flat_map(|x| (top..top + 3).map(move |y| (x, y)))
+            .collect();
+        for &xy in &cells {
+            world.add_new_particle(ParticleType::Concrete, xy, false);
+        }
+        world.add_rigid_body(&cells);
+        cells
+    }
+
+    fn run(world: &mut World, ticks: usize) {
+        let mut frame = Frame::for_world(world);
+        for _ in 0..ticks {
+            world.update_all();
+            world.draw_and_refresh(&mut frame, false);
+        }
+    }
+
+    #[test]
+    fn falls_as_a_unit_and_lands_intact() {
+        let mut world = World::with_seed(24, 32, 8, 1);
+        let cells = dropped_block(&mut world, 6);
+        assert_eq!(world.rigid_body_count(), 1);
+        run(&mut world, 60);
+
+        // The same shape, sitting on the floor
+        let landed = cells_of(&world, ParticleType::Concrete);
+        let fell = world.height - 2 - cells.iter().map(|c| c.1).max().unwrap();
+        let moved: Vec<_> = cells.iter().map(|&(x, y)| (x, y + fell)).collect();
+        assert!(fell > 0);
+        assert_eq!(landed.len(), cells.len());
+        assert!(moved.iter().all(|xy| landed.contains(xy)));
+        assert_eq!(world.rigid_body_count(), 1);
+    }
+
+    #[test]
+    fn hard_landing_shatters() {
+        // Far enough to hit the floor at full speed
+        let mut world = World::with_seed(24, 96, 8, 1);
+        let cells = dropped_block(&mut world, 80);
+        run(&mut world, 40);
+
+        assert_eq!(world.rigid_body_count(), 0);
+        assert!(cells_of(&world, ParticleType::Concrete).is_empty());
+        assert_eq!(cells_of(&world, ParticleType::Sand).len(), cells.len());
+    }
+
+    #[test]
+    fn landing_in_water_displaces_it() {
+        let mut world = World::with_seed(24, 32, 8, 1);
+        for x in 1..23 {
+            for y in 24..31 {
+                world.add_new_particle(ParticleType::Water, (x, y), false);
+            }
+        }
+        run(&mut world, 5);
+        let water = cells_of(&world, ParticleType::Water).len();
+        let cells = dropped_block(&mut world, 12);
+        run(&mut world, 120);
+
+        let concrete = cells_of(&world, ParticleType::Concrete);
+        assert_eq!(concrete.len(), cells.len());
+        assert_eq!(cells_of(&world, ParticleType::Water).len(), water);
+        // Sunk to the bottom, with the water pushed up around it
+        assert!(concrete.iter().all(|&(_, y)| y >= 28));
+        assert!(cells_of(&world, ParticleType::Water)
+            .iter()
+            .any(|&(_, y)| y < 24));
+    }
+}
@@ -0,0 +1,685 @@
+//! On-disk world format. A world file is the magic bytes `SAND`, a
+//! little-endian `u32` format version, then the bincode-encoded body for that
+//! version.
+//!
+//! When the body changes, the old body struct keeps its version number and
+//! gets a `From` impl into the next one, so `read_body` can decode any older
+//! file and step it forward to the current layout.
+
+use super::*;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"SAND";
+const FORMAT_VERSION: u32 = 7;
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(std::io::Error),
+    Encoding(bincode::Error),
+    NotAWorldFile,
+    NotAReplayFile,
+    UnsupportedVersion(u32),
+    UnsupportedReplayVersion(u32),
+    UnknownMaterial(String),
+    Invalid(&'static str),
+}
+
+impl std::fmt::Display for SaveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SaveError::Io(e) => write!(f, "{}", e),
+            SaveError::Encoding(e) => write!(f, "could not encode/decode world: {}", e),
+            SaveError::NotAWorldFile => write!(f, "not a world file"),
+            SaveError::NotAReplayFile => write!(f, "not a replay file"),
+            SaveError::UnsupportedVersion(v) => write!(
+                f,
+                "world file version {} is newer than this version of sand understands ({})",
+                v, FORMAT_VERSION
+            ),
+            SaveError::UnsupportedReplayVersion(v) => {
+                write!(
+                    f,
+                    "replay version {} isn't one this version of sand can play",
+                    v
+                )
+            }
+            SaveError::UnknownMaterial(label) => {
+                write!(f, "world uses a particle type that isn't loaded: {}", label)
+            }
+            SaveError::Invalid(reason) => write!(f, "invalid world file: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+impl From<std::io::Error> for SaveError {
+    fn from(value: std::io::Error) -> Self {
+        SaveError::Io(value)
+    }
+}
+
+impl From<bincode::Error> for SaveError {
+    fn from(value: bincode::Error) -> Self {
+        SaveError::Encoding(value)
+    }
+}
+
+// Enough to put the RNG back exactly where it was, so a loaded world carries
+// on the same way the saved one would have.
+#[derive(Serialize, Deserialize)]
+struct RngState {
+    seed: [u8; 32],
+    stream: u64,
+    word_pos: u128,
+}
+
+// Border through Acid; nothing else existed while version 1 was current
+const V1_BUILTIN_COUNT: usize = 13;
+
+/// Version 1, from before particle types could be loaded at runtime. Particle
+/// types were always the built-in ones, in order.
+#[derive(Deserialize)]
+struct WorldFileV1 {
+    width: usize,
+    height: usize,
+    chunk_size: usize,
+    seed: u64,
+    rng: RngState,
+    particles: Vec<ParticleV1>,
+    sources: Vec<((usize, usize), ParticleSource)>,
+    portals: Vec<((usize, usize), Portal)>,
+}
+
+impl From<WorldFileV1> for WorldFileV2 {
+    fn from(v1: WorldFileV1) -> Self {
+        Self {
+            materials: ParticleType::all()
+                .take(V1_BUILTIN_COUNT)
+                .map(|t| t.properties().label.clone())
+                .collect(),
+            width: v1.width,
+            height: v1.height,
+            chunk_size: v1.chunk_size,
+            seed: v1.seed,
+            rng: v1.rng,
+            particles: v1.particles,
+            sources: v1.sources,
+            portals: v1.portals,
+        }
+    }
+}
+
+/// Version 2, from before particles had a temperature.
+#[derive(Deserialize)]
+struct WorldFileV2 {
+    materials: Vec<String>,
+    width: usize,
+    height: usize,
+    chunk_size: usize,
+    seed: u64,
+    rng: RngState,
+    particles: Vec<ParticleV1>,
+    sources: Vec<((usize, usize), ParticleSource)>,
+    portals: Vec<((usize, usize), Portal)>,
+}
+
+impl From<WorldFileV2> for WorldFileV3 {
+    fn from(v2: WorldFileV2) -> Self {
+        // Particle types aren't remapped to the loaded ones yet, so go by label.
+        // Anything unknown gets caught when they are.
+        let initial_temperature = |particle: &ParticleV1| {
+            v2.materials
+                .get(particle.particle_type.0 as usize)
+                .and_then(|label| ParticleType::from_label(label))
+                .map_or(AMBIENT_TEMPERATURE, |t| t.properties().initial_temperature)
+        };
+        let particles = v2
+            .particles
+            .into_iter()
+            .map(|p| {
+                let temperature = initial_temperature(&p);
+                p.into_particle(temperature)
+            })
+            .collect();
+
+        Self {
+            materials: v2.materials,
+            width: v2.width,
+            height: v2.height,
+            chunk_size: v2.chunk_size,
+            seed: v2.seed,
+            rng: v2.rng,
+            particles,
+            sources: v2.sources,
+            portals: v2.portals,
+        }
+    }
+}
+
+/// Version 3, from before particles were packed into flags.
+#[derive(Deserialize)]
+struct WorldFileV3 {
+    materials: Vec<String>,
+    width: usize,
+    height: usize,
+    chunk_size: usize,
+    seed: u64,
+    rng: RngState,
+    particles: Vec<ParticleV3>,
+    sources: Vec<((usize, usize), ParticleSource)>,
+    portals: Vec<((usize, usize), Portal)>,
+}
+
+impl From<WorldFileV3> for WorldFileV4 {
+    fn from(v3: WorldFileV3) -> Self {
+        Self {
+            materials: v3.materials,
+            width: v3.width,
+            height: v3.height,
+            chunk_size: v3.chunk_size,
+            seed: v3.seed,
+            rng: v3.rng,
+            particles: v3
+                .particles
+                .into_iter()
+                .map(ParticleV3::into_particle)
+                .collect(),
+            sources: v3.sources,
+            portals: v3.portals,
+        }
+    }
+}
+
+/// Version 4, from before rigid bodies.
+#[derive(Deserialize)]
+struct WorldFileV4 {
+    materials: Vec<String>,
+    width: usize,
+    height: usize,
+    chunk_size: usize,
+    seed: u64,
+    rng: RngState,
+    particles: Vec<ParticleV4>,
+    sources: Vec<((usize, usize), ParticleSource)>,
+    portals: Vec<((usize, usize), Portal)>,
+}
+
+impl From<WorldFileV4> for WorldFileV5 {
+    fn from(v4: WorldFileV4) -> Self {
+        Self {
+            materials: v4.materials,
+            width: v4.width,
+            height: v4.height,
+            chunk_size: v4.chunk_size,
+            seed: v4.seed,
+            rng: v4.rng,
+            particles: v4.particles,
+            sources: v4.sources,
+            portals: v4.portals,
+            rigid_bodies: vec![],
+        }
+    }
+}
+
+/// Version 5, from before electricity.
+#[derive(Deserialize)]
+struct WorldFileV5 {
+    materials: Vec<String>,
+    width: usize,
+    height: usize,
+    chunk_size: usize,
+    seed: u64,
+    rng: RngState,
+    particles: Vec<ParticleV4>,
+    sources: Vec<((usize, usize), ParticleSource)>,
+    portals: Vec<((usize, usize), Portal)>,
+    rigid_bodies: Vec<RigidBody>,
+}
+
+impl From<WorldFileV5> for WorldFileV6 {
+    fn from(v5: WorldFileV5) -> Self {
+        Self {
+            materials: v5.materials,
+            width: v5.width,
+            height: v5.height,
+            chunk_size: v5.chunk_size,
+            seed: v5.seed,
+            rng: v5.rng,
+            particles: v5
+                .particles
+                .into_iter()
+                .map(ParticleV4::into_particle)
+                .collect(),
+            sources: v5.sources,
+            portals: v5.portals,
+            rigid_bodies: v5.rigid_bodies,
+        }
+    }
+}
+
+/// Version 6, from before fans and wind.
+#[derive(Deserialize)]
+struct WorldFileV6 {
+    materials: Vec<String>,
+    width: usize,
+    height: usize,
+    chunk_size: usize,
+    seed: u64,
+    rng: RngState,
+    particles: Vec<SavedParticle>,
+    sources: Vec<((usize, usize), ParticleSource)>,
+    portals: Vec<((usize, usize), Portal)>,
+    rigid_bodies: Vec<RigidBody>,
+}
+
+impl From<WorldFileV6> for WorldFile {
+    fn from(v6: WorldFileV6) -> Self {
+        Self {
+            materials: v6.materials,
+            width: v6.width,
+            height: v6.height,
+            chunk_size: v6.chunk_size,
+            seed: v6.seed,
+            rng: v6.rng,
+            particles: v6.particles,
+            sources: v6.sources,
+            portals: v6.portals,
+            rigid_bodies: v6.rigid_bodies,
+            fans: vec![],
+            wind: 0,
+        }
+    }
+}
+
+/// Current (version 7) body of a world file.
+#[derive(Serialize, Deserialize)]
+struct WorldFile {
+    // Labels of the particle types used in this file, indexed by the particle
+    // types as saved. The registry they're loaded into might have them in a
+    // different order (or not at all).
+    materials: Vec<String>,
+    width: usize,
+    height: usize,
+    chunk_size: usize,
+    seed: u64,
+    rng: RngState,
+    // Row-major, width * height of them
+    particles: Vec<SavedParticle>,
+    sources: Vec<((usize, usize), ParticleSource)>,
+    portals: Vec<((usize, usize), Portal)>,
+    rigid_bodies: Vec<RigidBody>,
+    fans: Vec<((usize, usize), Fan)>,
+    wind: i8,
+}
+
+fn read_body<R: Read>(version: u32, reader: R) -> Result<WorldFile, SaveError> {
+    match version {
+        1 => {
+            let v1: WorldFileV1 = bincode::deserialize_from(reader)?;
+            let v4 = WorldFileV4::from(WorldFileV3::from(WorldFileV2::from(v1)));
+            Ok(WorldFileV6::from(WorldFileV5::from(v4)).into())
+        }
+        2 => {
+            let v2: WorldFileV2 = bincode::deserialize_from(reader)?;
+            let v4 = WorldFileV4::from(WorldFileV3::from(v2));
+            Ok(WorldFileV6::from(WorldFileV5::from(v4)).into())
+        }
+        3 => {
+            let v3: WorldFileV3 = bincode::deserialize_from(reader)?;
+            let v4 = WorldFileV4::from(v3);
+            Ok(WorldFileV6::from(WorldFileV5::from(v4)).into())
+        }
+        4 => {
+            let v4: WorldFileV4 = bincode::deserialize_from(reader)?;
+            Ok(WorldFileV6::from(WorldFileV5::from(v4)).into())
+        }
+        5 => {
+            let v5: WorldFileV5 = bincode::deserialize_from(reader)?;
+            Ok(WorldFileV6::from(v5).into())
+        }
+        6 => Ok(bincode::deserialize_from::<_, WorldFileV6>(reader)?.into()),
+        FORMAT_VERSION => Ok(bincode::deserialize_from(reader)?),
+        v => Err(SaveError::UnsupportedVersion(v)),
+    }
+}
+
+impl WorldFile {
+    fn validate(&self) -> Result<(), SaveError> {
+        if self.chunk_size == 0
+            || !self.width.is_multiple_of(self.chunk_size)
+            || !self.height.is_multiple_of(self.chunk_size)
+        {
+            return Err(SaveError::Invalid("size is not a multiple of chunk size"));
+        }
+        let size = self.width.checked_mul(self.height);
+        if size != Some(self.particles.len()) {
+            return Err(SaveError::Invalid("wrong number of particles"));
+        }
+
+        let in_bounds = |xy: &(usize, usize)| xy.0 < self.width && xy.1 < self.height;
+        if !self.sources.iter().all(|(xy, _)| in_bounds(xy)) {
+            return Err(SaveError::Invalid("source out of bounds"));
+        }
+        if !self
+            .portals
+            .iter()
+            .all(|(xy, portal)| in_bounds(xy) && portal.partner_xy.is_none_or(|p| in_bounds(&p)))
+        {
+            return Err(SaveError::Invalid("portal out of bounds"));
+        }
+        // Portals come in pairs that point at each other
+        let partners: HashMap<(usize, usize), Option<(usize, usize)>> = self
+            .portals
+            .iter()
+            .map(|(xy, portal)| (*xy, portal.partner_xy))
+            .collect();
+        if !partners
+            .iter()
+            .all(|(xy, partner_xy)| partner_xy.is_none_or(|p| partners.get(&p) == Some(&Some(*xy))))
+        {
+            return Err(SaveError::Invalid("portal partner doesn't link back"));
+        }
+        if !self
+            .rigid_bodies
+            .iter()
+            .all(|body| body.in_bounds(self.width, self.height))
+        {
+            return Err(SaveError::Invalid("rigid body out of bounds"));
+        }
+        if !self.fans.iter().all(|(xy, _)| in_bounds(xy)) {
+            return Err(SaveError::Invalid("fan out of bounds"));
+        }
+        Ok(())
+    }
+
+    /// Point every particle type in the file at the matching one in the
+    /// registry, by label.
+    fn remap_particle_types(&mut self) -> Result<(), SaveError> {
+        let remap = self
+            .materials
+            .iter()
+            .map(|label| {
+                ParticleType::from_label(label)
+                    .ok_or_else(|| SaveError::UnknownMaterial(label.clone()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let lookup = |particle_type: &mut ParticleType| {
+            *particle_type = *remap
+                .get(particle_type.0 as usize)
+                .ok_or(SaveError::Invalid("particle type out of range"))?;
+            Ok::<_, SaveError>(())
+        };
+
+        for particle in self.particles.iter_mut() {
+            lookup(&mut particle.particle_type)?;
+        }
+        for (_, source) in self.sources.iter_mut() {
+            lookup(&mut source.particle_type)?;
+        }
+        Ok(())
+    }
+}
+
+impl World {
+    pub fn save<W: Write>(&self, mut writer: W) -> Result<(), SaveError> {
+        let mut particles = Vec::with_capacity(self.width * self.height);
+        let mut sources = vec![];
+        let mut portals = vec![];
+        let mut fans = vec![];
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                particles.push(SavedParticle::new(
+                    *self.get_particle((x, y)),
+                    *self.get_extras((x, y)),
+                ));
+                if let Some(source) = &self.source_grid[(x, y)] {
+                    sources.push(((x, y), source.clone()));
+                }
+                if let Some(portal) = &self.portal_grid[(x, y)] {
+                    portals.push(((x, y), portal.clone()));
+                }
+                if let Some(fan) = &self.fan_grid[(x, y)] {
+                    fans.push(((x, y), fan.clone()));
+                }
+            }
+        }
+
+        let body = WorldFile {
+            materials: ParticleType::all()
+                .map(|t| t.properties().label.clone())
+                .collect(),
+            width: self.width,
+            height: self.height,
+            chunk_size: self.chunk_size,
+            seed: self.seed,
+            rng: RngState {
+                seed: self.rng.get_seed(),
+                stream: self.rng.get_stream(),
+                word_pos: self.rng.get_word_pos(),
+            },
+            particles,
+            sources,
+            portals,
+            rigid_bodies: self.rigid_bodies.clone(),
+            fans,
+            wind: self.wind,
+        };
+
+        writer.write_all(MAGIC)?;
+        writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        bincode::serialize_into(&mut writer, &body)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn load<R: Read>(mut reader: R) -> Result<World, SaveError> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(SaveError::NotAWorldFile);
+        }
+
+        let mut version = [0; 4];
+        reader.read_exact(&mut version)?;
+        let mut body = read_body(u32::from_le_bytes(version), reader)?;
+        body.validate()?;
+        body.remap_particle_types()?;
+
+        let mut world = World::with_seed(body.width, body.height, body.chunk_size, body.seed);
+
+        for (i, saved) in body.particles.into_iter().enumerate() {
+            let (mut particle, extras) = saved.split();
+            particle.refresh();
+            let xy = (i % body.width, i / body.width);
+            world.put_particle(xy, particle);
+            world.put_extras(xy, extras);
+        }
+        for (xy, source) in body.sources {
+            world.source_grid[xy] = Some(source);
+        }
+        for (xy, portal) in body.portals {
+            world.portal_grid[xy] = Some(portal);
+            world.has_portals = true;
+        }
+        world.rigid_bodies = body.rigid_bodies;
+        for (xy, fan) in body.fans {
+            world.fan_grid[xy] = Some(fan);
+        }
+        world.wind = body.wind;
+
+        world.rng = WorldRng::from_seed(body.rng.seed);
+        world.rng.set_stream(body.rng.stream);
+        world.rng.set_word_pos(body.rng.word_pos);
+
+        Ok(world)
+    }
+
+    pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), SaveError> {
+        self.save(BufWriter::new(File::create(path)?))
+    }
+
+    pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<World, SaveError> {
+        World::load(BufReader::new(File::open(path)?))
+    }
+}
+
+// The bytes a world saves as, for tests to compare worlds by
+#[cfg(test)]
+pub(super) fn saved(world: &World) -> Vec<u8> {
+    let mut bytes = vec![];
+    world.save(&mut bytes).unwrap();
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The same small world saved by each older version of sand: sand, water,
+    // wood and acid, a water source and a pair of portals
+    const OLD_VERSIONS: [&[u8]; 6] = [
+        include_bytes!("../../tests/fixtures/world_v1.sand"),
+        include_bytes!("../../tests/fixtures/world_v2.sand"),
+        include_bytes!("../../tests/fixtures/world_v3.sand"),
+        include_bytes!("../../tests/fixtures/world_v4.sand"),
+        include_bytes!("../../tests/fixtures/world_v5.sand"),
+        include_bytes!("../../tests/fixtures/world_v6.sand"),
+    ];
+
+    #[test]
+    fn old_versions_load() {
+        for (i, bytes) in OLD_VERSIONS.into_iter().enumerate() {
+            let version = i as u32 + 1;
+            assert_eq!(bytes[4..8], version.to_le_bytes());
+            let world = World::load(bytes).unwrap_or_else(|e| panic!("version {}: {}", version, e));
+
+            assert_eq!((world.width(), world.height()), (16, 16));
+            assert_eq!(world.seed(), 7);
+            for (xy, particle_type) in [
+                ((3, 3), ParticleType::Sand),
+                ((4, 3), ParticleType::Sand),
+                ((3, 10), ParticleType::Water),
+                ((10, 10), ParticleType::Wood),
+                ((12, 3), ParticleType::Acid),
+                ((0, 0), ParticleType::Border),
+                ((8, 8), ParticleType::Empty),
+            ] {
+                assert_eq!(
+                    world.get_particle(xy).particle_type,
+                    particle_type,
+                    "version {} at {:?}",
+                    version,
+                    xy
+                );
+            }
+            assert_eq!(
+                world.source_grid[(5, 5)].as_ref().map(|s| s.particle_type),
+                Some(ParticleType::Water)
+            );
+            assert_eq!(
+                world.portal_grid[(9, 13)]
+                    .as_ref()
+                    .and_then(|p| p.partner_xy),
+                Some((6, 13))
+            );
+            assert_eq!(
+                world.portal_grid[(6, 13)]
+                    .as_ref()
+                    .and_then(|p| p.partner_xy),
+                Some((9, 13))
+            );
+
+            // And it comes back out as the current version
+            let bytes = saved(&world);
+            assert_eq!(bytes[4..8], FORMAT_VERSION.to_le_bytes());
+            assert_eq!(saved(&World::load(bytes.as_slice()).unwrap()), bytes);
+        }
+    }
+
+    #[test]
+    fn round_trip_keeps_everything() {
+        let mut world = World::with_seed(32, 32, 8, 3);
+        for x in 4..28 {
+            world.add_new_particle(ParticleType::Sand, (x, 4), false);
+            world.add_new_particle(ParticleType::Wood, (x, 20), false);
+        }
+        world.add_new_source(ParticleType::Water, (16, 2), false, false);
+        world.add_new_fan((2, 10), Direction::Right, 3, false);
+        world.set_wind(-2);
+        let mut frame = Frame::for_world(&world);
+        for _ in 0..20 {
+            world.update_all();
+            world.draw_and_refresh(&mut frame, false);
+        }
+
+        let bytes = saved(&world);
+        let mut loaded = World::load(bytes.as_slice()).unwrap();
+        assert_eq!(saved(&loaded), bytes);
+
+        // And carries on the same, once the original has everything awake
+        // like a loaded world does
+        world.wake_everything();
+        for _ in 0..10 {
+            world.update_all();
+            world.draw_and_refresh(&mut frame, false);
+            loaded.update_all();
+            loaded.draw_and_refresh(&mut frame, false);
+        }
+        assert_eq!(saved(&loaded), saved(&world));
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(matches!(
+            World::load(&b"PNG\0\x07\0\0\0"[..]),
+            Err(SaveError::NotAWorldFile)
+        ));
+        assert!(matches!(
+            World::load(&b"SAND\x63\0\0\0"[..]),
+            Err(SaveError::UnsupportedVersion(99))
+        ));
+    }
+
+    #[test]
+    fn rejects_inconsistent_worlds() {
+        let mut world = World::with_seed(16, 16, 8, 0);
+        let color = PColor::new(0, 0, 255);
+        world.add_new_portal((3, 5), None, Direction::Up, color);
+        world.add_new_portal((9, 5), Some((3, 5)), Direction::Up, color);
+        let bytes = saved(&world);
+
+        let tampered = |change: &dyn Fn(&mut WorldFile)| {
+            let mut file: WorldFile = bincode::deserialize(&bytes[8..]).unwrap();
+            change(&mut file);
+            let mut bytes = bytes[..8].to_vec();
+            bytes.extend(bincode::serialize(&file).unwrap());
+            match World::load(bytes.as_slice()) {
+                Err(SaveError::Invalid(message)) => message,
+                _ => panic!("expected an invalid world"),
+            }
+        };
+        // Too big to count the particles in
+        assert_eq!(
+            tampered(&|file| {
+                file.chunk_size = 1 << 32;
+                file.width = 1 << 32;
+                file.height = 1 << 32;
+            }),
+            "wrong number of particles"
+        );
+        assert_eq!(
+            tampered(&|file| file.portals[0].1.partner_xy = Some((4, 4))),
+            "portal partner doesn't link back"
+        );
+        assert_eq!(
+            tampered(&|file| file.portals.retain(|(xy, _)| *xy == (9, 5))),
+            "portal partner doesn't link back"
+        );
+    }
+}
@@ -0,0 +1,158 @@
+//! Runs scripted behaviours in a world. Materials can only be loaded once per
+//! process, so this has a test binary to itself, and every test shares the
+//! same materials.
+
+#![cfg(feature = "scripting")]
+
+use sand::*;
+use std::path::PathBuf;
+use std::sync::Once;
+
+const MATERIALS: &str = r##"
+[[material]]
+label = "Termite"
+color = "#e8d8a0"
+weight = inf
+behavior = "termite"
+
+[[material]]
+label = "Broken"
+color = "#ff0000"
+weight = inf
+behavior = "broken"
+
+[[material]]
+label = "Painter"
+color = "#0000ff"
+weight = inf
+behavior = "painter"
+"##;
+
+const PAINTER: &str = r#"fn update(api) { api.replace_with_new(0, -1, "Sand"); }"#;
+
+// Scripts of our own, in a directory of their own, so they can be changed
+fn scratch_scripts() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("sand-scripting-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn setup() {
+    static SETUP: Once = Once::new();
+    SETUP.call_once(|| {
+        let examples = concat!(env!("CARGO_MANIFEST_DIR"), "/scripts");
+        assert_eq!(register_script_behaviors(examples).unwrap(), 1);
+
+        let scratch = scratch_scripts();
+        std::fs::write(
+            scratch.join("broken.rhai"),
+            "fn update(api) { api.neighbour(5, 0); }",
+        )
+        .unwrap();
+        std::fs::write(scratch.join("painter.rhai"), PAINTER).unwrap();
+        assert_eq!(register_script_behaviors(&scratch).unwrap(), 2);
+
+        load_materials_from_str(MATERIALS).unwrap();
+    });
+}
+
+fn count(world: &World, particle_type: ParticleType) -> usize {
+    let mut count = 0;
+    for y in 0..world.height() {
+        for x in 0..world.width() {
+            if world.get_particle((x, y)).particle_type == particle_type {
+                count += 1;
+            }
+        }
+    }
+    count
+}
+
+fn run(world: &mut World, ticks: usize) {
+    let mut frame = Frame::for_world(world);
+    for _ in 0..ticks {
+        world.update_all();
+        world.draw_and_refresh(&mut frame, false);
+    }
+}
+
+#[test]
+fn termite_script_eats_wood() {
+    setup();
+    let termite = ParticleType::from_label("Termite").unwrap();
+
+    let mut world = World::with_seed(16, 16, 8, 0);
+    for y in 1..15 {
+        for x in 8..15 {
+            world.add_new_particle(ParticleType::Wood, (x, y), false);
+        }
+    }
+    world.add_new_particle(termite, (4, 8), false);
+    let wood = count(&world, ParticleType::Wood);
+    run(&mut world, 500);
+
+    assert_eq!(count(&world, termite), 1);
+    assert!(count(&world, ParticleType::Wood) < wood);
+}
+
+#[test]
+fn runtime_errors_are_reported_once() {
+    setup();
+    let broken = ParticleType::from_label("Broken").unwrap();
+    let mut world = World::with_seed(16, 16, 8, 0);
+    world.add_new_particle(broken, (8, 8), false);
+    run(&mut world, 3);
+
+    let is_broken = |e: &MaterialError| e.to_string().contains("broken.rhai");
+    let errors = take_script_errors();
+    let error = errors.iter().find(|e| is_broken(e)).unwrap().to_string();
+    assert!(error.contains("in update"), "{}", error);
+    assert!(
+        error.contains("(5, 0) isn't next to the particle"),
+        "{}",
+        error
+    );
+
+    run(&mut world, 3);
+    assert!(!take_script_errors().iter().any(is_broken));
+}
+
+#[test]
+fn reloading_picks_up_changes() {
+    setup();
+    let painter = ParticleType::from_label("Painter").unwrap();
+    let painted = |world: &mut World| {
+        world.add_new_particle(painter, (8, 8), false);
+        run(world, 1);
+        world.get_particle((8, 7)).particle_type
+    };
+    let path = scratch_scripts().join("painter.rhai");
+
+    assert_eq!(
+        painted(&mut World::with_seed(16, 16, 8, 0)),
+        ParticleType::Sand
+    );
+
+    std::fs::write(&path, PAINTER.replace("Sand", "Salt")).unwrap();
+    assert!(reload_scripts().is_empty());
+    assert_eq!(
+        painted(&mut World::with_seed(16, 16, 8, 0)),
+        ParticleType::Salt
+    );
+
+    // A script that no longer compiles leaves the old one running
+    std::fs::write(&path, "fn update(api) {").unwrap();
+    let errors = reload_scripts();
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].to_string().contains("painter.rhai"));
+    assert_eq!(
+        painted(&mut World::with_seed(16, 16, 8, 0)),
+        ParticleType::Salt
+    );
+}
+
+#[test]
+fn script_errors_name_the_script() {
+    let error = ScriptBehavior::new("broken.rhai", "fn update(api) {").unwrap_err();
+    assert!(error.to_string().starts_with("broken.rhai: "));
+}